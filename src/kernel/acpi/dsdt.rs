@@ -0,0 +1,55 @@
+//! Just enough of the DSDT's AML to find the sleep type values of a sleep
+//! state, which are defined by `\_Sx_` package objects.
+
+use super::Table;
+
+const NAME_OP: u8 = 0x08;
+const ROOT_PREFIX: u8 = b'\\';
+const PACKAGE_OP: u8 = 0x12;
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0a;
+const ONES_OP: u8 = 0xff;
+
+/// The values written to the `SLP_TYP` fields of the PM1a and PM1b control
+/// registers to enter a sleep state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepType {
+    pub a: u8,
+    pub b: u8,
+}
+
+/// Returns the sleep type values of sleep state `S<state>`.
+pub fn sleep_type(dsdt: &Table, state: u8) -> Option<SleepType> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+    let aml = dsdt.data();
+
+    let start = (1..aml.len().saturating_sub(4)).find(|&i| {
+        aml[i..i + 4] == name
+            && (aml[i - 1] == NAME_OP
+                || (aml[i - 1] == ROOT_PREFIX && i >= 2 && aml[i - 2] == NAME_OP))
+    })?;
+
+    // Package: PackageOp PkgLength NumElements PackageElement...
+    let mut aml = &aml[start + 4..];
+    if *aml.first()? != PACKAGE_OP {
+        return None;
+    }
+    let pkg_length_bytes = 1 + usize::from(*aml.get(1)? >> 6);
+    aml = aml.get(1 + pkg_length_bytes + 1..)?;
+
+    let (a, aml) = integer(aml)?;
+    let (b, _) = integer(aml)?;
+    Some(SleepType { a, b })
+}
+
+/// Parses an integer constant small enough for a `SLP_TYP` field.
+fn integer(aml: &[u8]) -> Option<(u8, &[u8])> {
+    match *aml.first()? {
+        ZERO_OP => Some((0, &aml[1..])),
+        ONE_OP => Some((1, &aml[1..])),
+        ONES_OP => Some((0xff, &aml[1..])),
+        BYTE_PREFIX => Some((*aml.get(1)?, &aml[2..])),
+        _ => None,
+    }
+}
@@ -0,0 +1,71 @@
+//! The fixed ACPI description table, describing the power management hardware.
+
+use super::{read_u16, read_u32, read_u64, AcpiError, GenericAddress, Table};
+use x86_64::PhysAddr;
+
+/// Set if the reset register is supported.
+const RESET_REG_SUP: u32 = 1 << 10;
+/// Set in the IA-PC boot architecture flags if there is an 8042 controller.
+const BOOT_ARCH_8042: u16 = 1 << 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    /// The physical address of the DSDT.
+    pub dsdt: PhysAddr,
+    /// The legacy IRQ of the SCI interrupt.
+    pub sci_interrupt: u16,
+    /// The I/O port to which `acpi_enable` is written to enter ACPI mode, or zero.
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    /// The I/O ports of the PM1a and PM1b control registers, zero if absent.
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    /// The I/O port of the power management timer, zero if absent.
+    pub pm_timer_block: u32,
+    /// The register resetting the system, if supported.
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+    /// Whether there is an 8042 keyboard controller; assumed if the table is too
+    /// old to say.
+    pub has_8042: bool,
+}
+
+impl Fadt {
+    pub(super) fn parse(table: Table) -> Result<Self, AcpiError> {
+        // ACPI 1.0 tables end after the flags
+        table.require(116)?;
+        let bytes = table.bytes();
+
+        let mut fadt = Fadt {
+            dsdt: PhysAddr::new(u64::from(read_u32(bytes, 40))),
+            sci_interrupt: read_u16(bytes, 46),
+            smi_command_port: read_u32(bytes, 48),
+            acpi_enable: bytes[52],
+            acpi_disable: bytes[53],
+            pm1a_control_block: read_u32(bytes, 64),
+            pm1b_control_block: read_u32(bytes, 68),
+            pm_timer_block: read_u32(bytes, 76),
+            reset_register: None,
+            reset_value: 0,
+            has_8042: true,
+        };
+
+        if table.revision() >= 2 && bytes.len() >= 129 {
+            let flags = read_u32(bytes, 112);
+            if flags & RESET_REG_SUP != 0 {
+                fadt.reset_register = Some(GenericAddress::parse(bytes, 116));
+                fadt.reset_value = bytes[128];
+            }
+            fadt.has_8042 = read_u16(bytes, 109) & BOOT_ARCH_8042 != 0;
+        }
+        if bytes.len() >= 148 {
+            // prefer the 64-bit DSDT pointer of ACPI 2.0 tables
+            let x_dsdt = read_u64(bytes, 140);
+            if x_dsdt != 0 {
+                fadt.dsdt = PhysAddr::new(x_dsdt);
+            }
+        }
+        Ok(fadt)
+    }
+}
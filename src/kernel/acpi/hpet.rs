@@ -0,0 +1,37 @@
+//! The HPET description table, locating the high precision event timer.
+
+use super::{read_u16, read_u32, AcpiError, GenericAddress, Table};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    /// The registers of the timer block.
+    pub base_address: GenericAddress,
+    /// The sequence number of the timer block.
+    pub number: u8,
+    /// The minimal number of counter ticks between periodic interrupts.
+    pub minimum_tick: u16,
+    pub comparators: u8,
+    /// Whether the main counter is 64 bits wide.
+    pub counter_64bit: bool,
+    /// Whether the timer can replace the PIT and RTC interrupts.
+    pub legacy_replacement: bool,
+    pub pci_vendor_id: u16,
+}
+
+impl Hpet {
+    pub(super) fn parse(table: Table) -> Result<Self, AcpiError> {
+        table.require(56)?;
+        let bytes = table.bytes();
+
+        let block_id = read_u32(bytes, 36);
+        Ok(Hpet {
+            base_address: GenericAddress::parse(bytes, 40),
+            number: bytes[52],
+            minimum_tick: read_u16(bytes, 53),
+            comparators: ((block_id >> 8) & 0x1f) as u8 + 1,
+            counter_64bit: block_id & (1 << 13) != 0,
+            legacy_replacement: block_id & (1 << 15) != 0,
+            pci_vendor_id: (block_id >> 16) as u16,
+        })
+    }
+}
@@ -0,0 +1,125 @@
+//! The multiple APIC description table, listing the CPUs and interrupt
+//! controllers of the system.
+
+use super::{read_u16, read_u32, read_u64, AcpiError, Table};
+use crate::kernel::devices::apic::{io::Trigger, ApicInfo, InterruptOverride, IoApicInfo};
+use alloc::vec::Vec;
+use x86_64::PhysAddr;
+
+const PROCESSOR_LOCAL_APIC: u8 = 0;
+const IO_APIC: u8 = 1;
+const INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const PROCESSOR_LOCAL_X2APIC: u8 = 9;
+
+/// Set if the system also has 8259 PICs.
+const PCAT_COMPAT: u32 = 1 << 0;
+
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+#[derive(Debug, Clone)]
+pub struct Madt {
+    /// The physical address of the local APIC registers.
+    pub local_apic_address: PhysAddr,
+    /// Whether the system also has 8259 PICs, which must be disabled.
+    pub has_pics: bool,
+    pub processors: Vec<Processor>,
+    pub io_apics: Vec<IoApicInfo>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+/// A CPU, identified by its local APIC.
+#[derive(Debug, Clone, Copy)]
+pub struct Processor {
+    /// The ACPI processor UID.
+    pub processor_id: u32,
+    pub apic_id: u32,
+    /// Whether the CPU can be started.
+    pub usable: bool,
+}
+
+impl Madt {
+    pub(super) fn parse(table: Table) -> Result<Self, AcpiError> {
+        table.require(44)?;
+        let bytes = table.bytes();
+        let mut madt = Madt {
+            local_apic_address: PhysAddr::new(u64::from(read_u32(bytes, 36))),
+            has_pics: read_u32(bytes, 40) & PCAT_COMPAT != 0,
+            processors: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+        };
+
+        // variable-length entries, each starting with its type and length
+        let mut offset = 44;
+        while offset + 2 <= bytes.len() {
+            let kind = bytes[offset];
+            let len = usize::from(bytes[offset + 1]);
+            if len < 2 || offset + len > bytes.len() {
+                return Err(AcpiError::Truncated(table.signature()));
+            }
+            let entry = &bytes[offset..offset + len];
+            madt.parse_entry(kind, entry);
+            offset += len;
+        }
+        Ok(madt)
+    }
+
+    fn parse_entry(&mut self, kind: u8, entry: &[u8]) {
+        match kind {
+            PROCESSOR_LOCAL_APIC if entry.len() >= 8 => {
+                let flags = read_u32(entry, 4);
+                self.processors.push(Processor {
+                    processor_id: u32::from(entry[2]),
+                    apic_id: u32::from(entry[3]),
+                    usable: flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0,
+                });
+            }
+            PROCESSOR_LOCAL_X2APIC if entry.len() >= 16 => {
+                let flags = read_u32(entry, 8);
+                self.processors.push(Processor {
+                    processor_id: read_u32(entry, 12),
+                    apic_id: read_u32(entry, 4),
+                    usable: flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0,
+                });
+            }
+            IO_APIC if entry.len() >= 12 => self.io_apics.push(IoApicInfo {
+                id: entry[2],
+                address: PhysAddr::new(u64::from(read_u32(entry, 4))),
+                gsi_base: read_u32(entry, 8),
+            }),
+            INTERRUPT_SOURCE_OVERRIDE if entry.len() >= 10 => {
+                let flags = read_u16(entry, 8);
+                self.overrides.push(InterruptOverride {
+                    irq: entry[3],
+                    gsi: read_u32(entry, 4),
+                    trigger: trigger(flags),
+                });
+            }
+            LOCAL_APIC_ADDRESS_OVERRIDE if entry.len() >= 12 => {
+                self.local_apic_address = PhysAddr::new(read_u64(entry, 4));
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the interrupt controller configuration described by the MADT.
+    pub fn apic_info(&self) -> ApicInfo {
+        ApicInfo {
+            local_apic_address: self.local_apic_address,
+            io_apics: self.io_apics.clone(),
+            overrides: self.overrides.clone(),
+        }
+    }
+}
+
+/// Decodes the MPS INTI flags of an interrupt source override.
+///
+/// Lines that conform to the bus specification are ISA lines here.
+fn trigger(flags: u16) -> Trigger {
+    Trigger {
+        active_low: flags & 0b11 == 0b11,
+        level: (flags >> 2) & 0b11 == 0b11,
+    }
+}
@@ -0,0 +1,304 @@
+//! # ACPI tables
+//!
+//! Finds the RSDP in the BIOS area, walks the RSDT (or XSDT) and parses the
+//! tables the kernel needs. All tables are read through the physical memory
+//! mapping set up by the bootloader, and every table's checksum is validated
+//! before it is used.
+
+use crate::kernel::memory::phys_to_virt;
+use crate::println;
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use core::{convert::TryInto, fmt, slice, str};
+use x86_64::PhysAddr;
+
+pub mod dsdt;
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+
+use fadt::Fadt;
+use hpet::Hpet;
+use madt::Madt;
+
+/// The size of the header shared by all system description tables.
+pub const HEADER_SIZE: usize = 36;
+
+static ACPI: OnceCell<Acpi> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// No valid RSDP was found in the BIOS area.
+    NoRsdp,
+    /// A table's checksum is wrong.
+    BadChecksum([u8; 4]),
+    /// A table is shorter than its contents require.
+    Truncated([u8; 4]),
+}
+
+/// The parsed ACPI tables.
+#[derive(Debug)]
+pub struct Acpi {
+    /// The ACPI revision of the RSDP (0 for ACPI 1.0, 2 for later versions).
+    pub revision: u8,
+    /// All tables listed by the RSDT or XSDT.
+    pub tables: Vec<Table>,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+}
+
+impl Acpi {
+    /// Returns the first table with the given signature.
+    pub fn find_table(&self, signature: &[u8; 4]) -> Option<Table> {
+        self.tables
+            .iter()
+            .find(|table| &table.signature() == signature)
+            .copied()
+    }
+
+    /// Returns the differentiated system description table, which holds the
+    /// AML code describing the system.
+    pub fn dsdt(&self) -> Option<Table> {
+        Table::new(self.fadt?.dsdt).ok()
+    }
+}
+
+/// Parses the ACPI tables.
+///
+/// Must be called once, after the heap was initialized.
+pub fn init() -> Result<&'static Acpi, AcpiError> {
+    let rsdp = find_rsdp().ok_or(AcpiError::NoRsdp)?;
+    let acpi = parse(rsdp)?;
+    ACPI.try_init_once(|| acpi)
+        .expect("acpi::init should only be called once");
+    Ok(ACPI.try_get().unwrap())
+}
+
+/// Returns the tables parsed by `init`.
+pub fn get() -> Option<&'static Acpi> {
+    ACPI.try_get().ok()
+}
+
+fn parse(rsdp: Rsdp) -> Result<Acpi, AcpiError> {
+    // the XSDT holds 64-bit pointers and replaces the RSDT since ACPI 2.0
+    let (root, entry_size) = match rsdp.xsdt_address {
+        Some(xsdt) => (Table::new(xsdt)?, 8),
+        None => (Table::new(rsdp.rsdt_address)?, 4),
+    };
+
+    let mut tables = Vec::new();
+    for entry in root.data().chunks_exact(entry_size) {
+        let addr = match entry_size {
+            8 => u64::from_le_bytes(entry.try_into().unwrap()),
+            _ => u64::from(u32::from_le_bytes(entry.try_into().unwrap())),
+        };
+        // one broken table must not hide the others
+        match Table::new(PhysAddr::new(addr)) {
+            Ok(table) => tables.push(table),
+            Err(err) => println!("WARNING: skipping ACPI table at {:#x}: {:?}", addr, err),
+        }
+    }
+
+    let mut acpi = Acpi {
+        revision: rsdp.revision,
+        tables,
+        madt: None,
+        fadt: None,
+        hpet: None,
+    };
+    if let Some(table) = acpi.find_table(b"APIC") {
+        acpi.madt = Some(Madt::parse(table)?);
+    }
+    if let Some(table) = acpi.find_table(b"FACP") {
+        acpi.fadt = Some(Fadt::parse(table)?);
+    }
+    if let Some(table) = acpi.find_table(b"HPET") {
+        acpi.hpet = Some(Hpet::parse(table)?);
+    }
+    Ok(acpi)
+}
+
+/// The root system description pointer.
+struct Rsdp {
+    revision: u8,
+    rsdt_address: PhysAddr,
+    xsdt_address: Option<PhysAddr>,
+}
+
+/// Searches the first KiB of the extended BIOS data area and the BIOS ROM for
+/// the RSDP.
+fn find_rsdp() -> Option<Rsdp> {
+    // the real-mode segment of the EBDA is stored at 0x40e
+    let ebda = u64::from(read_u16(physical_bytes(PhysAddr::new(0x40e), 2), 0)) << 4;
+    let areas = [(ebda, 1024), (0xe0000, 0x20000)];
+
+    for &(start, len) in areas.iter().filter(|&&(start, _)| start != 0) {
+        for offset in (0..len).step_by(16) {
+            let addr = PhysAddr::new(start + offset);
+            if let Some(rsdp) = parse_rsdp(addr) {
+                return Some(rsdp);
+            }
+        }
+    }
+    None
+}
+
+fn parse_rsdp(addr: PhysAddr) -> Option<Rsdp> {
+    let bytes = physical_bytes(addr, 20);
+    if &bytes[..8] != b"RSD PTR " || checksum(bytes) != 0 {
+        return None;
+    }
+
+    let revision = bytes[15];
+    let rsdt_address = PhysAddr::new(u64::from(read_u32(bytes, 16)));
+    if revision < 2 {
+        return Some(Rsdp {
+            revision,
+            rsdt_address,
+            xsdt_address: None,
+        });
+    }
+
+    // ACPI 2.0 extends the structure and covers it with a second checksum
+    let length = read_u32(physical_bytes(addr, 24), 20) as usize;
+    let bytes = physical_bytes(addr, length.max(36));
+    if checksum(bytes) != 0 {
+        return None;
+    }
+    let xsdt_address = match read_u64(bytes, 24) {
+        0 => None,
+        addr => Some(PhysAddr::new(addr)),
+    };
+    Some(Rsdp {
+        revision,
+        rsdt_address,
+        xsdt_address,
+    })
+}
+
+/// A system description table whose checksum was validated.
+#[derive(Clone, Copy)]
+pub struct Table {
+    addr: PhysAddr,
+    bytes: &'static [u8],
+}
+
+impl Table {
+    fn new(addr: PhysAddr) -> Result<Self, AcpiError> {
+        let header = physical_bytes(addr, HEADER_SIZE);
+        let signature = header[..4].try_into().unwrap();
+        let length = read_u32(header, 4) as usize;
+        if length < HEADER_SIZE {
+            return Err(AcpiError::Truncated(signature));
+        }
+
+        let bytes = physical_bytes(addr, length);
+        if checksum(bytes) != 0 {
+            return Err(AcpiError::BadChecksum(signature));
+        }
+        Ok(Table { addr, bytes })
+    }
+
+    /// Returns the physical address of the table.
+    pub fn address(&self) -> PhysAddr {
+        self.addr
+    }
+
+    pub fn signature(&self) -> [u8; 4] {
+        self.bytes[..4].try_into().unwrap()
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.bytes[8]
+    }
+
+    /// Returns the whole table, including its header.
+    pub fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// Returns the contents of the table following its header.
+    pub fn data(&self) -> &'static [u8] {
+        &self.bytes[HEADER_SIZE..]
+    }
+
+    /// Returns an error unless the table is at least `len` bytes long.
+    fn require(&self, len: usize) -> Result<(), AcpiError> {
+        if self.bytes.len() < len {
+            return Err(AcpiError::Truncated(self.signature()));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let signature = self.signature();
+        f.debug_struct("Table")
+            .field("signature", &str::from_utf8(&signature).unwrap_or("????"))
+            .field("address", &self.addr)
+            .field("length", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Returns the `len` bytes of physical memory starting at `addr`.
+fn physical_bytes(addr: PhysAddr, len: usize) -> &'static [u8] {
+    unsafe { slice::from_raw_parts(phys_to_virt(addr).as_ptr(), len) }
+}
+
+/// Returns the sum of all bytes; a valid ACPI structure sums up to zero.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// A generic address structure, describing a register in some address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub address_space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfig,
+    Other(u8),
+}
+
+impl GenericAddress {
+    /// The size of the structure in tables.
+    pub const SIZE: usize = 12;
+
+    fn parse(bytes: &[u8], offset: usize) -> Self {
+        GenericAddress {
+            address_space: match bytes[offset] {
+                0 => AddressSpace::SystemMemory,
+                1 => AddressSpace::SystemIo,
+                2 => AddressSpace::PciConfig,
+                other => AddressSpace::Other(other),
+            },
+            bit_width: bytes[offset + 1],
+            bit_offset: bytes[offset + 2],
+            access_size: bytes[offset + 3],
+            address: read_u64(bytes, offset + 4),
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use super::{align_up, Locked};
+use super::{
+    align_up,
+    stats::{AllocatorStats, Counters, HeapStats},
+    Locked,
+};
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr;
 
@@ -7,6 +11,7 @@ pub struct BumpAllocator {
     heap_end: usize,
     next: usize,
     allocations: usize,
+    counters: Counters,
 }
 
 impl BumpAllocator {
@@ -17,6 +22,7 @@ impl BumpAllocator {
             heap_end: 0,
             next: 0,
             allocations: 0,
+            counters: Counters::new(),
         }
     }
 
@@ -31,6 +37,15 @@ impl BumpAllocator {
     }
 }
 
+impl AllocatorStats for BumpAllocator {
+    fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new(self.heap_end - self.heap_start, &self.counters);
+        // freed memory is only reused once every allocation is freed
+        stats.bytes_free = self.heap_end - self.next;
+        stats
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<BumpAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut bump = self.lock(); // get a mutable reference
@@ -46,13 +61,15 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
         } else {
             bump.next = alloc_end;
             bump.allocations += 1;
+            bump.counters.record_alloc(layout.size());
             alloc_start as *mut u8
         }
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&self, _ptr: *mut u8, layout: Layout) {
         let mut bump = self.lock(); // get a mutable reference
 
+        bump.counters.record_free(layout.size());
         bump.allocations -= 1;
         if bump.allocations == 0 {
             bump.next = bump.heap_start;
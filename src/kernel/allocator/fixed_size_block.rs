@@ -1,4 +1,7 @@
-use super::Locked;
+use super::{
+    stats::{AllocatorStats, Counters, HeapStats, SizeClassStats},
+    Locked,
+};
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::{
     mem,
@@ -26,6 +29,11 @@ struct ListNode {
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
     fallback_allocator: linked_list_allocator::Heap,
+    counters: Counters,
+    /// Number of blocks of each size class handed out to callers.
+    blocks_in_use: [usize; BLOCK_SIZES.len()],
+    /// Number of blocks in each of the `list_heads` lists.
+    blocks_free: [usize; BLOCK_SIZES.len()],
 }
 
 impl FixedSizeBlockAllocator {
@@ -34,6 +42,9 @@ impl FixedSizeBlockAllocator {
         FixedSizeBlockAllocator {
             list_heads: [None; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            counters: Counters::new(),
+            blocks_in_use: [0; BLOCK_SIZES.len()],
+            blocks_free: [0; BLOCK_SIZES.len()],
         }
     }
 
@@ -47,7 +58,21 @@ impl FixedSizeBlockAllocator {
     }
 
     /// Allocates using the fallback allocator.
+    ///
+    /// Grows the heap once if the fallback allocator is out of memory.
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        // enough for the allocation even if the top of the heap is in use
+        let min_size = layout.size() + layout.align();
+        let grown = super::grow_heap(self.fallback_allocator.top(), min_size);
+        if grown == 0 {
+            return ptr::null_mut();
+        }
+        unsafe { self.fallback_allocator.extend(grown) };
+
         match self.fallback_allocator.allocate_first_fit(layout) {
             Ok(ptr) => ptr.as_ptr(),
             Err(_) => ptr::null_mut(),
@@ -55,14 +80,29 @@ impl FixedSizeBlockAllocator {
     }
 }
 
+impl AllocatorStats for FixedSizeBlockAllocator {
+    fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new(self.fallback_allocator.size(), &self.counters);
+        for (index, &block_size) in BLOCK_SIZES.iter().enumerate() {
+            stats.push_size_class(SizeClassStats {
+                block_size,
+                in_use: self.blocks_in_use[index],
+                free: self.blocks_free[index],
+            });
+        }
+        stats
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
-        match list_index(&layout) {
+        let ptr = match list_index(&layout) {
             Some(index) => {
-                match allocator.list_heads[index].take() {
+                let ptr = match allocator.list_heads[index].take() {
                     Some(node) => {
                         allocator.list_heads[index] = node.next.take();
+                        allocator.blocks_free[index] -= 1;
                         node as *mut ListNode as *mut u8
                     }
                     None => {
@@ -73,14 +113,23 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                         let layout = Layout::from_size_align(block_size, block_align).unwrap();
                         allocator.fallback_alloc(layout)
                     }
+                };
+                if !ptr.is_null() {
+                    allocator.blocks_in_use[index] += 1;
                 }
+                ptr
             }
             None => allocator.fallback_alloc(layout),
+        };
+        if !ptr.is_null() {
+            allocator.counters.record_alloc(layout.size());
         }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
+        allocator.counters.record_free(layout.size());
         match list_index(&layout) {
             Some(index) => {
                 let new_node = ListNode {
@@ -92,6 +141,8 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 let new_node_ptr = ptr as *mut ListNode;
                 new_node_ptr.write(new_node);
                 allocator.list_heads[index] = Some(&mut *new_node_ptr);
+                allocator.blocks_in_use[index] -= 1;
+                allocator.blocks_free[index] += 1;
             }
             None => {
                 let ptr = NonNull::new(ptr).unwrap();
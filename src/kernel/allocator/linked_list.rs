@@ -1,4 +1,8 @@
-use super::{align_up, Locked};
+use super::{
+    align_up,
+    stats::{AllocatorStats, Counters, HeapStats},
+    Locked,
+};
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::{mem, ptr};
 
@@ -23,6 +27,8 @@ impl ListNode {
 
 pub struct LinkedListAllocator {
     head: ListNode,
+    heap_size: usize,
+    counters: Counters,
 }
 
 impl LinkedListAllocator {
@@ -30,6 +36,8 @@ impl LinkedListAllocator {
     pub const fn new() -> Self {
         Self {
             head: ListNode::new(0),
+            heap_size: 0,
+            counters: Counters::new(),
         }
     }
 
@@ -39,21 +47,79 @@ impl LinkedListAllocator {
     /// heap bounds are valid and that the heap is unused. This method must be
     /// called only once.
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_size = heap_size;
         self.add_free_region(heap_start, heap_size);
     }
 
-    /// Adds the given memory region to the front of the list.
+    /// Adds the given memory region to the list.
+    ///
+    /// The list is kept sorted by address, and the region is merged with the
+    /// free regions directly before and after it.
     unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
         // ensure that the freed region is capable of holding ListNode
         assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
         assert!(size >= mem::size_of::<ListNode>());
 
-        // create a new list node and append it at the start of the list
+        // find the last region that starts before the freed one
+        let head_addr = self.head.start_addr();
+        let mut current = &mut self.head;
+        while current
+            .next
+            .as_ref()
+            .map_or(false, |next| next.start_addr() < addr)
+        {
+            current = current.next.as_mut().unwrap();
+        }
+
+        // merge with the following region
+        let mut size = size;
+        let mut next = current.next.take();
+        if let Some(region) = next.take() {
+            if addr + size == region.start_addr() {
+                size += region.size;
+                next = region.next.take();
+            } else {
+                next = Some(region);
+            }
+        }
+
+        // merge with the preceding region
+        if current.start_addr() != head_addr && current.end_addr() == addr {
+            current.size += size;
+            current.next = next;
+            return;
+        }
+
+        // insert a new list node after `current`
         let mut node = ListNode::new(size);
-        node.next = self.head.next.take();
+        node.next = next;
         let node_ptr = addr as *mut ListNode;
         node_ptr.write(node);
-        self.head.next = Some(&mut *node_ptr)
+        current.next = Some(&mut *node_ptr)
+    }
+
+    /// Removes the free region starting at `addr` from the list if it holds
+    /// at least `size` bytes.
+    fn take_region_at(&mut self, addr: usize, size: usize) -> Option<&'static mut ListNode> {
+        let mut current = &mut self.head;
+        while current
+            .next
+            .as_ref()
+            .map_or(false, |next| next.start_addr() < addr)
+        {
+            current = current.next.as_mut().unwrap();
+        }
+
+        let suitable = current.next.as_ref().map_or(false, |region| {
+            region.start_addr() == addr && region.size >= size
+        });
+        if !suitable {
+            return None;
+        }
+
+        let region = current.next.take().unwrap();
+        current.next = region.next.take();
+        Some(region)
     }
 
     /// Looks for a free region with the given size and alignment and removes
@@ -85,7 +151,13 @@ impl LinkedListAllocator {
     ///
     /// Returns the allocation start address on success.
     fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+        let padding = alloc_start - region.start_addr();
+        if padding > 0 && padding < mem::size_of::<ListNode>() {
+            // padding before the allocation must be able to hold a ListNode
+            // so that it can stay in the list
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         if alloc_end > region.end_addr() {
@@ -118,6 +190,12 @@ impl LinkedListAllocator {
     }
 }
 
+impl AllocatorStats for LinkedListAllocator {
+    fn stats(&self) -> HeapStats {
+        HeapStats::new(self.heap_size, &self.counters)
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         // perform layout adjustments
@@ -125,11 +203,16 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
         let mut allocator = self.lock();
 
         if let Some((region, alloc_start)) = allocator.find_region(size, align) {
+            let region_start = region.start_addr();
             let alloc_end = alloc_start.checked_add(size).expect("overflow");
             let excess_size = region.end_addr() - alloc_end;
             if excess_size > 0 {
                 allocator.add_free_region(alloc_end, excess_size);
             }
+            if alloc_start > region_start {
+                allocator.add_free_region(region_start, alloc_start - region_start);
+            }
+            allocator.counters.record_alloc(layout.size());
             alloc_start as *mut u8
         } else {
             ptr::null_mut()
@@ -140,6 +223,66 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
         // perform layout adjustments
         let (size, _) = LinkedListAllocator::size_align(layout);
 
-        self.lock().add_free_region(ptr as usize, size)
+        let mut allocator = self.lock();
+        allocator.counters.record_free(layout.size());
+        allocator.add_free_region(ptr as usize, size)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let (old_size, _) = LinkedListAllocator::size_align(layout);
+        let (new_size, _) = LinkedListAllocator::size_align(new_layout);
+        let addr = ptr as usize;
+
+        {
+            let mut allocator = self.lock();
+            let resized = if new_size <= old_size {
+                // shrink in place if the freed tail can hold a ListNode
+                let excess_size = old_size - new_size;
+                if excess_size == 0 {
+                    true
+                } else if excess_size >= mem::size_of::<ListNode>() {
+                    allocator.add_free_region(addr + new_size, excess_size);
+                    true
+                } else {
+                    false
+                }
+            } else {
+                // grow in place if the following region is free and large enough
+                let needed = new_size - old_size;
+                match allocator.take_region_at(addr + old_size, needed) {
+                    Some(region) => {
+                        let region_end = region.end_addr();
+                        let excess_size = region_end - (addr + new_size);
+                        if excess_size == 0 {
+                            true
+                        } else if excess_size >= mem::size_of::<ListNode>() {
+                            allocator.add_free_region(addr + new_size, excess_size);
+                            true
+                        } else {
+                            // put the region back untouched
+                            allocator
+                                .add_free_region(addr + old_size, region_end - addr - old_size);
+                            false
+                        }
+                    }
+                    None => false,
+                }
+            };
+
+            if resized {
+                allocator.counters.record_free(layout.size());
+                allocator.counters.record_alloc(new_layout.size());
+                return ptr;
+            }
+        }
+
+        // move the allocation
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_layout.size()));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
     }
 }
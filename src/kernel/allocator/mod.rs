@@ -1,7 +1,13 @@
-use fixed_size_block::FixedSizeBlockAllocator;
+use crate::kernel::memory;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use stats::{AllocatorStats, HeapStats};
 use x86_64::{
+    instructions::interrupts,
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, page::PageRangeInclusive, FrameAllocator, Mapper, Page, PageTableFlags,
+        Size4KiB,
     },
     VirtAddr,
 };
@@ -9,25 +15,121 @@ use x86_64::{
 pub mod bump;
 pub mod fixed_size_block;
 pub mod linked_list;
+pub mod slab;
+pub mod stats;
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+
+/// The minimum number of bytes mapped whenever the heap grows.
+const HEAP_GROW_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// The size up to which the heap may grow, `HEAP_MAX_SIZE` by default.
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(HEAP_MAX_SIZE);
+
+#[cfg(not(any(
+    feature = "alloc-bump",
+    feature = "alloc-linked-list",
+    feature = "alloc-fixed-block",
+    feature = "alloc-slab"
+)))]
+compile_error!("select a heap allocator through one of the `alloc-*` features");
+
+#[cfg(any(
+    all(feature = "alloc-bump", feature = "alloc-linked-list"),
+    all(feature = "alloc-bump", feature = "alloc-fixed-block"),
+    all(feature = "alloc-bump", feature = "alloc-slab"),
+    all(feature = "alloc-linked-list", feature = "alloc-fixed-block"),
+    all(feature = "alloc-linked-list", feature = "alloc-slab"),
+    all(feature = "alloc-fixed-block", feature = "alloc-slab")
+))]
+compile_error!("only one of the `alloc-*` features can be enabled");
+
+/// The allocator backing the kernel heap, selected through cargo features.
+#[cfg(feature = "alloc-bump")]
+pub type HeapAllocator = bump::BumpAllocator;
+#[cfg(feature = "alloc-linked-list")]
+pub type HeapAllocator = linked_list::LinkedListAllocator;
+#[cfg(feature = "alloc-fixed-block")]
+pub type HeapAllocator = fixed_size_block::FixedSizeBlockAllocator;
+#[cfg(feature = "alloc-slab")]
+pub type HeapAllocator = slab::SlabAllocator;
 
 #[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+static ALLOCATOR: Locked<HeapAllocator> = Locked::new(HeapAllocator::new());
 
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
-    };
+    map_heap_pages(heap_pages(HEAP_START, HEAP_SIZE), mapper, frame_allocator)?;
+
+    unsafe {
+        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    }
+
+    Ok(())
+}
+
+/// Returns a snapshot of the usage of the global allocator.
+///
+/// Print it with `serial_println!("{}", heap_stats())` to inspect the heap
+/// from the host.
+pub fn heap_stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
+}
+
+/// Sets the size up to which the heap may grow on demand.
+///
+/// Limits below the current heap size only prevent further growth.
+pub fn set_heap_limit(limit: usize) {
+    HEAP_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Maps at least `min_size` more bytes of heap memory at `heap_end`, the
+/// current end of the heap.
+///
+/// Returns the number of bytes that were mapped. This is zero once the heap
+/// reached its limit, physical memory ran out or the kernel memory was not
+/// installed yet.
+fn grow_heap(heap_end: usize, min_size: usize) -> usize {
+    let limit = HEAP_START + HEAP_LIMIT.load(Ordering::Relaxed);
+    let size = align_up(
+        min_size.max(HEAP_GROW_SIZE),
+        Page::<Size4KiB>::SIZE as usize,
+    );
+    let size = size.min(limit.saturating_sub(heap_end));
+    if size < min_size {
+        return 0;
+    }
+
+    let mut mapped = 0;
+    memory::with_kernel_memory(|mapper, frame_allocator| {
+        for page in heap_pages(heap_end, size) {
+            if map_heap_pages(Page::range_inclusive(page, page), mapper, frame_allocator).is_err() {
+                break;
+            }
+            mapped += Page::<Size4KiB>::SIZE as usize;
+        }
+    });
+    mapped
+}
 
+/// Returns the pages covering `size` bytes starting at `start`.
+fn heap_pages(start: usize, size: usize) -> PageRangeInclusive {
+    let heap_start = VirtAddr::new(start as u64);
+    let heap_end = heap_start + size - 1u64;
+    let heap_start_page = Page::containing_address(heap_start);
+    let heap_end_page = Page::containing_address(heap_end);
+    Page::range_inclusive(heap_start_page, heap_end_page)
+}
+
+fn map_heap_pages(
+    page_range: PageRangeInclusive,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
     for page in page_range {
         let frame = frame_allocator
             .allocate_frame()
@@ -36,14 +138,13 @@ pub fn init_heap(
         unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
     }
 
-    unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
-    }
-
     Ok(())
 }
 
 /// A wrapper around spin::Mutex to permit trait implementations.
+///
+/// Interrupts are disabled while it is locked: code that allocates with them
+/// disabled would otherwise spin forever on a holder preempted on its CPU.
 pub struct Locked<A> {
     inner: spin::Mutex<A>,
 }
@@ -55,8 +156,44 @@ impl<A> Locked<A> {
         }
     }
 
-    pub fn lock(&self) -> spin::MutexGuard<A> {
-        self.inner.lock()
+    pub fn lock(&self) -> LockedGuard<A> {
+        let enabled = interrupts::are_enabled();
+        interrupts::disable();
+        LockedGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            enabled,
+        }
+    }
+}
+
+/// The guard of a `Locked`, which enables interrupts again once it is
+/// dropped, if they were enabled before.
+pub struct LockedGuard<'a, A> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, A>>,
+    enabled: bool,
+}
+
+impl<A> Deref for LockedGuard<'_, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.guard
+    }
+}
+
+impl<A> DerefMut for LockedGuard<'_, A> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.guard
+    }
+}
+
+impl<A> Drop for LockedGuard<'_, A> {
+    fn drop(&mut self) {
+        // unlock first, so that no interrupt arrives while it is held
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.enabled {
+            interrupts::enable();
+        }
     }
 }
 
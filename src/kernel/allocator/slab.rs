@@ -0,0 +1,494 @@
+use super::{
+    align_up,
+    stats::{AllocatorStats, Counters, HeapStats, SizeClassStats},
+    Locked,
+};
+use crate::kernel::memory;
+use alloc::alloc::{handle_alloc_error, GlobalAlloc, Layout};
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+use x86_64::{
+    structures::paging::{PhysFrame, Size4KiB},
+    VirtAddr,
+};
+
+/// The size of a slab; every slab is exactly one page frame.
+const SLAB_SIZE: usize = 4096;
+
+/// Header at the start of every slab page.
+struct Slab {
+    /// First free object in this slab.
+    free: Option<NonNull<FreeObject>>,
+    /// Number of objects handed out from this slab.
+    in_use: usize,
+    prev: Option<NonNull<Slab>>,
+    next: Option<NonNull<Slab>>,
+}
+
+/// Stored in every free object of a slab.
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+/// A named cache of equally sized objects.
+///
+/// Objects are carved out of whole page frames taken from the kernel's frame
+/// allocator; these are accessed through the physical memory mapping, so no
+/// page table changes are needed. Slabs that still have free objects are kept
+/// in a list, full slabs are only found again through the objects freed into
+/// them, and slabs that become empty are returned to the frame allocator.
+///
+/// Objects of exactly one page, e.g. page tables, get a page each, without a
+/// slab header.
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    align: usize,
+    /// Slabs with at least one free object.
+    partial: Option<NonNull<Slab>>,
+    slabs: usize,
+    objects_in_use: usize,
+}
+
+// The slabs are only reachable through their cache.
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    /// Creates an empty cache for objects of the given size and alignment.
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        // every object must be able to hold a `FreeObject` while it is free
+        let align = if align > mem::align_of::<FreeObject>() {
+            align
+        } else {
+            mem::align_of::<FreeObject>()
+        };
+        let size = if size > mem::size_of::<FreeObject>() {
+            size
+        } else {
+            mem::size_of::<FreeObject>()
+        };
+        SlabCache {
+            name,
+            object_size: (size + align - 1) & !(align - 1),
+            align,
+            partial: None,
+            slabs: 0,
+            objects_in_use: 0,
+        }
+    }
+
+    /// Creates an empty cache for objects of type `T`.
+    pub const fn for_type<T>(name: &'static str) -> Self {
+        Self::new(name, mem::size_of::<T>(), mem::align_of::<T>())
+    }
+
+    /// Returns the name of this cache.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the size of the objects in this cache.
+    pub fn object_size(&self) -> usize {
+        self.object_size
+    }
+
+    /// Returns the number of objects handed out by this cache.
+    pub fn objects_in_use(&self) -> usize {
+        self.objects_in_use
+    }
+
+    /// Returns the number of free objects in the slabs of this cache.
+    pub fn objects_free(&self) -> usize {
+        self.slabs * self.objects_per_slab() - self.objects_in_use
+    }
+
+    /// Returns the number of slab pages owned by this cache.
+    pub fn slabs(&self) -> usize {
+        self.slabs
+    }
+
+    /// Offset of the first object from the start of a slab.
+    fn first_object(&self) -> usize {
+        align_up(mem::size_of::<Slab>(), self.align)
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        if self.page_sized() {
+            return 1;
+        }
+        SLAB_SIZE.saturating_sub(self.first_object()) / self.object_size
+    }
+
+    /// Returns whether every object is a page of its own.
+    fn page_sized(&self) -> bool {
+        self.object_size == SLAB_SIZE
+    }
+
+    /// Allocates an object, taking a new slab page if all slabs are full.
+    pub fn allocate(&mut self) -> Option<NonNull<u8>> {
+        if self.page_sized() {
+            let frame = memory::allocate_frame()?;
+            let page = memory::phys_to_virt(frame.start_address());
+            self.slabs += 1;
+            self.objects_in_use += 1;
+            return NonNull::new(page.as_mut_ptr());
+        }
+
+        let mut slab_ptr = match self.partial {
+            Some(slab) => slab,
+            None => self.grow()?,
+        };
+        let slab = unsafe { slab_ptr.as_mut() };
+
+        let object = slab.free.expect("slab on partial list has no free object");
+        slab.free = unsafe { object.as_ref().next };
+        slab.in_use += 1;
+        self.objects_in_use += 1;
+
+        if slab.free.is_none() {
+            // full slabs are not tracked
+            unsafe { self.unlink(slab_ptr) };
+        }
+
+        Some(object.cast())
+    }
+
+    /// Returns an object to its slab.
+    ///
+    /// This method is unsafe because the caller must guarantee that `ptr` was
+    /// allocated from this cache and is no longer in use.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>) {
+        if self.page_sized() {
+            self.objects_in_use -= 1;
+            self.release(ptr.cast());
+            return;
+        }
+
+        let slab_addr = ptr.as_ptr() as usize & !(SLAB_SIZE - 1);
+        let mut slab_ptr = NonNull::new_unchecked(slab_addr as *mut Slab);
+        let slab = slab_ptr.as_mut();
+
+        let was_full = slab.free.is_none();
+        let object = ptr.cast::<FreeObject>();
+        object.as_ptr().write(FreeObject { next: slab.free });
+        slab.free = Some(object);
+        slab.in_use -= 1;
+        self.objects_in_use -= 1;
+
+        if slab.in_use == 0 {
+            // empty slabs go back to the frame allocator
+            if !was_full {
+                self.unlink(slab_ptr);
+            }
+            self.release(slab_ptr);
+        } else if was_full {
+            self.push(slab_ptr);
+        }
+    }
+
+    /// Takes a new slab page from the frame allocator and puts it on the
+    /// partial list.
+    fn grow(&mut self) -> Option<NonNull<Slab>> {
+        let objects = self.objects_per_slab();
+        assert!(
+            objects > 0,
+            "slab cache {}: objects do not fit a page",
+            self.name
+        );
+
+        let frame = memory::allocate_frame()?;
+        let base = memory::phys_to_virt(frame.start_address()).as_u64() as usize;
+
+        // thread all objects onto the slab's free list
+        let mut free = None;
+        for index in (0..objects).rev() {
+            let addr = base + self.first_object() + index * self.object_size;
+            let object = addr as *mut FreeObject;
+            unsafe { object.write(FreeObject { next: free }) };
+            free = NonNull::new(object);
+        }
+
+        let slab = base as *mut Slab;
+        unsafe {
+            slab.write(Slab {
+                free,
+                in_use: 0,
+                prev: None,
+                next: None,
+            });
+        }
+        let slab = NonNull::new(slab).unwrap();
+        self.slabs += 1;
+        unsafe { self.push(slab) };
+        Some(slab)
+    }
+
+    /// Returns an empty, unlinked slab page to the frame allocator.
+    unsafe fn release(&mut self, slab: NonNull<Slab>) {
+        let addr = VirtAddr::new(slab.as_ptr() as u64);
+        let frame = PhysFrame::<Size4KiB>::containing_address(memory::virt_to_phys(addr));
+        self.slabs -= 1;
+        memory::deallocate_frame(frame);
+    }
+
+    /// Pushes a slab to the front of the partial list.
+    unsafe fn push(&mut self, mut slab: NonNull<Slab>) {
+        slab.as_mut().prev = None;
+        slab.as_mut().next = self.partial;
+        if let Some(mut head) = self.partial {
+            head.as_mut().prev = Some(slab);
+        }
+        self.partial = Some(slab);
+    }
+
+    /// Removes a slab from the partial list.
+    unsafe fn unlink(&mut self, mut slab: NonNull<Slab>) {
+        let Slab { prev, next, .. } = *slab.as_ref();
+        match prev {
+            Some(mut prev) => prev.as_mut().next = next,
+            None => self.partial = next,
+        }
+        if let Some(mut next) = next {
+            next.as_mut().prev = prev;
+        }
+        slab.as_mut().prev = None;
+        slab.as_mut().next = None;
+    }
+}
+
+/// A named cache of objects of type `T`, which are handed out as `SlabBox`es.
+///
+/// Declared as a static next to the type, e.g.
+///
+/// ```ignore
+/// static TASKS: ObjectCache<Task> = ObjectCache::new("task");
+///
+/// let task = TASKS.alloc(Task::new(future));
+/// ```
+pub struct ObjectCache<T> {
+    cache: Locked<SlabCache>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ObjectCache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        ObjectCache {
+            cache: Locked::new(SlabCache::for_type::<T>(name)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves `value` into an object of the cache.
+    ///
+    /// Like `Box::new`, calls the allocation error handler if memory ran out.
+    pub fn alloc(&'static self, value: T) -> SlabBox<T> {
+        let ptr = self
+            .allocate_raw()
+            .unwrap_or_else(|| handle_alloc_error(Layout::new::<T>()));
+        unsafe { ptr.as_ptr().write(value) };
+        SlabBox { ptr, cache: self }
+    }
+
+    /// Allocates an uninitialized object.
+    pub fn allocate_raw(&self) -> Option<NonNull<T>> {
+        self.cache.lock().allocate().map(NonNull::cast)
+    }
+
+    /// Returns an object to the cache without dropping it.
+    ///
+    /// This method is unsafe because the caller must guarantee that `ptr` was
+    /// allocated from this cache and is no longer in use.
+    pub unsafe fn deallocate_raw(&self, ptr: NonNull<T>) {
+        self.cache.lock().deallocate(ptr.cast());
+    }
+
+    /// Returns the number of objects handed out by this cache.
+    pub fn objects_in_use(&self) -> usize {
+        self.cache.lock().objects_in_use()
+    }
+
+    /// Returns the number of slab pages owned by this cache.
+    pub fn slabs(&self) -> usize {
+        self.cache.lock().slabs()
+    }
+}
+
+/// An object allocated from an `ObjectCache`, which is dropped and returned
+/// to the cache when the box is dropped.
+pub struct SlabBox<T: 'static> {
+    ptr: NonNull<T>,
+    cache: &'static ObjectCache<T>,
+}
+
+// A `SlabBox` owns its object like a `Box`.
+unsafe impl<T: Send> Send for SlabBox<T> {}
+unsafe impl<T: Sync> Sync for SlabBox<T> {}
+
+impl<T> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache.deallocate_raw(self.ptr);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SlabBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// The object sizes served from slabs by `SlabAllocator`.
+///
+/// Unlike `FixedSizeBlockAllocator`, the sizes need not be powers of two.
+/// Objects of a class are aligned to the largest power of two dividing its size.
+const SIZE_CLASSES: &[usize] = &[8, 16, 32, 48, 64, 96, 128, 192, 256, 512, 1024, 2048];
+
+/// Choose an appropriate size class for the given layout.
+///
+/// Returns an index into the `SIZE_CLASSES` array.
+fn class_index(layout: &Layout) -> Option<usize> {
+    SIZE_CLASSES
+        .iter()
+        .position(|&s| s >= layout.size() && 1 << s.trailing_zeros() >= layout.align())
+}
+
+const fn size_class(index: usize) -> SlabCache {
+    let size = SIZE_CLASSES[index];
+    SlabCache::new("kmalloc", size, 1 << size.trailing_zeros())
+}
+
+/// A heap allocator serving small objects from slab caches.
+///
+/// Larger objects, and small ones while no frame allocator is installed, are
+/// served by a fallback heap.
+pub struct SlabAllocator {
+    caches: [SlabCache; SIZE_CLASSES.len()],
+    fallback_allocator: linked_list_allocator::Heap,
+    counters: Counters,
+}
+
+impl SlabAllocator {
+    /// Creates an empty SlabAllocator.
+    pub const fn new() -> Self {
+        SlabAllocator {
+            caches: [
+                size_class(0),
+                size_class(1),
+                size_class(2),
+                size_class(3),
+                size_class(4),
+                size_class(5),
+                size_class(6),
+                size_class(7),
+                size_class(8),
+                size_class(9),
+                size_class(10),
+                size_class(11),
+            ],
+            fallback_allocator: linked_list_allocator::Heap::empty(),
+            counters: Counters::new(),
+        }
+    }
+
+    /// Initialize the allocator with the given heap bounds for the fallback heap.
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused. This method must be
+    /// called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    }
+
+    /// Allocates using the fallback allocator.
+    ///
+    /// Grows the heap once if the fallback allocator is out of memory.
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        let min_size = layout.size() + layout.align();
+        let grown = super::grow_heap(self.fallback_allocator.top(), min_size);
+        if grown == 0 {
+            return ptr::null_mut();
+        }
+        unsafe { self.fallback_allocator.extend(grown) };
+
+        match self.fallback_allocator.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    /// Returns whether `ptr` was allocated from the fallback heap.
+    fn in_fallback_heap(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+        addr >= self.fallback_allocator.bottom() && addr < self.fallback_allocator.top()
+    }
+}
+
+impl AllocatorStats for SlabAllocator {
+    fn stats(&self) -> HeapStats {
+        let slab_pages: usize = self.caches.iter().map(|cache| cache.slabs()).sum();
+        let heap_size = self.fallback_allocator.size() + slab_pages * SLAB_SIZE;
+        let mut stats = HeapStats::new(heap_size, &self.counters);
+        for cache in self.caches.iter() {
+            stats.push_size_class(SizeClassStats {
+                block_size: cache.object_size(),
+                in_use: cache.objects_in_use(),
+                free: cache.objects_free(),
+            });
+        }
+        stats
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<SlabAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+        let ptr = match class_index(&layout) {
+            Some(index) => match allocator.caches[index].allocate() {
+                Some(ptr) => ptr.as_ptr(),
+                None => allocator.fallback_alloc(layout),
+            },
+            None => allocator.fallback_alloc(layout),
+        };
+        if !ptr.is_null() {
+            allocator.counters.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.lock();
+        allocator.counters.record_free(layout.size());
+        let ptr = NonNull::new(ptr).unwrap();
+        match class_index(&layout) {
+            Some(index) if !allocator.in_fallback_heap(ptr.as_ptr()) => {
+                allocator.caches[index].deallocate(ptr)
+            }
+            _ => allocator.fallback_allocator.deallocate(ptr, layout),
+        }
+    }
+}
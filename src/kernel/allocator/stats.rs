@@ -0,0 +1,128 @@
+use core::fmt;
+
+/// The maximum number of size classes reported by `HeapStats`.
+pub const MAX_SIZE_CLASSES: usize = 16;
+
+/// A snapshot of the usage of a heap allocator.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Size of the memory region managed by the allocator.
+    pub heap_size: usize,
+    /// Bytes currently handed out to callers.
+    pub bytes_allocated: usize,
+    /// Bytes of the heap not handed out to callers.
+    pub bytes_free: usize,
+    /// The highest value `bytes_allocated` ever reached.
+    pub peak_allocated: usize,
+    /// Number of successful allocations.
+    pub allocations: usize,
+    /// Number of deallocations.
+    pub frees: usize,
+    size_classes: [SizeClassStats; MAX_SIZE_CLASSES],
+    size_class_count: usize,
+}
+
+/// Occupancy of a single size class of a block-based allocator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeClassStats {
+    /// Size of the blocks in this class.
+    pub block_size: usize,
+    /// Blocks currently handed out to callers.
+    pub in_use: usize,
+    /// Blocks cached for reuse.
+    pub free: usize,
+}
+
+impl HeapStats {
+    pub(super) fn new(heap_size: usize, counters: &Counters) -> Self {
+        HeapStats {
+            heap_size,
+            bytes_allocated: counters.bytes_allocated,
+            bytes_free: heap_size.saturating_sub(counters.bytes_allocated),
+            peak_allocated: counters.peak_allocated,
+            allocations: counters.allocations,
+            frees: counters.frees,
+            size_classes: [SizeClassStats::default(); MAX_SIZE_CLASSES],
+            size_class_count: 0,
+        }
+    }
+
+    pub(super) fn push_size_class(&mut self, class: SizeClassStats) {
+        self.size_classes[self.size_class_count] = class;
+        self.size_class_count += 1;
+    }
+
+    /// Returns the per-size-class occupancy, empty for allocators without
+    /// size classes.
+    pub fn size_classes(&self) -> &[SizeClassStats] {
+        &self.size_classes[..self.size_class_count]
+    }
+
+    /// Returns the number of allocations that were never freed.
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.frees
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "heap: {} of {} bytes allocated, {} free, peak {}",
+            self.bytes_allocated, self.heap_size, self.bytes_free, self.peak_allocated
+        )?;
+        write!(
+            f,
+            "      {} allocations, {} frees, {} live",
+            self.allocations,
+            self.frees,
+            self.live_allocations()
+        )?;
+        for class in self.size_classes() {
+            write!(
+                f,
+                "\n      {:>5} B: {} in use, {} free",
+                class.block_size, class.in_use, class.free
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Usage counters kept by every allocator.
+pub(super) struct Counters {
+    bytes_allocated: usize,
+    peak_allocated: usize,
+    allocations: usize,
+    frees: usize,
+}
+
+impl Counters {
+    pub const fn new() -> Self {
+        Counters {
+            bytes_allocated: 0,
+            peak_allocated: 0,
+            allocations: 0,
+            frees: 0,
+        }
+    }
+
+    /// Records a successful allocation of `size` bytes.
+    pub fn record_alloc(&mut self, size: usize) {
+        self.bytes_allocated += size;
+        self.peak_allocated = self.peak_allocated.max(self.bytes_allocated);
+        self.allocations += 1;
+    }
+
+    /// Records the deallocation of `size` bytes.
+    pub fn record_free(&mut self, size: usize) {
+        self.bytes_allocated -= size;
+        self.frees += 1;
+    }
+}
+
+/// Allocators that can report their usage.
+pub trait AllocatorStats {
+    /// Returns a snapshot of the allocator's usage.
+    fn stats(&self) -> HeapStats;
+}
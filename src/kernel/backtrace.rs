@@ -0,0 +1,160 @@
+//! # Stack backtraces
+//!
+//! Walks the chain of saved frame pointers (the target spec keeps them in every
+//! function) and symbolizes return addresses with the kernel's symbol table.
+//!
+//! The symbol table is embedded into the `.ksyms` section after linking by
+//! `tools/embed-symbols.sh`, which the cargo runner calls before booting. It is
+//! the text output of `nm -n -C`: one `<address> <type> <name>` line per
+//! symbol, sorted by address and padded with zero bytes.
+
+use core::fmt;
+
+/// Size reserved for the symbol table in the kernel image.
+const KSYMS_SIZE: usize = 512 * 1024;
+
+/// Frames walked at most, in case the chain is corrupted or cyclic.
+const MAX_DEPTH: usize = 64;
+
+/// Frames larger than this are considered a corrupted frame pointer.
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+#[used]
+#[link_section = ".ksyms"]
+static KSYMS: [u8; KSYMS_SIZE] = [0; KSYMS_SIZE];
+
+/// Returns the frame pointer of the calling function.
+#[inline(always)]
+pub fn current_frame() -> usize {
+    let rbp: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack)) };
+    rbp
+}
+
+/// Returns the frame pointer of the code interrupted by the calling
+/// interrupt handler.
+///
+/// Must be called directly from an `x86-interrupt` handler, whose prologue
+/// saved the interrupted frame pointer.
+#[inline(always)]
+pub fn interrupted_frame() -> usize {
+    unsafe { *(current_frame() as *const usize) }
+}
+
+/// Returns the frame pointer of the code interrupted by the interrupt handler
+/// that called the calling function.
+///
+/// Must be called directly from an `#[inline(never)]` function, which was
+/// called directly from an `x86-interrupt` handler.
+#[inline(always)]
+pub fn handler_interrupted_frame() -> usize {
+    // the caller's frame holds the handler's, which holds the interrupted one
+    unsafe { *(*(current_frame() as *const usize) as *const usize) }
+}
+
+/// Returns an iterator over the return addresses of the call stack starting
+/// at the frame `rbp`.
+pub fn frames(rbp: usize) -> Frames {
+    Frames { rbp, depth: 0 }
+}
+
+/// An iterator over the return addresses of a call stack.
+pub struct Frames {
+    rbp: usize,
+    depth: usize,
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.rbp == 0 || self.rbp % 8 != 0 || self.depth >= MAX_DEPTH {
+            return None;
+        }
+
+        // a frame starts with the caller's frame pointer and the return address
+        let frame = self.rbp as *const usize;
+        let (caller_rbp, return_address) = unsafe { (*frame, *frame.add(1)) };
+        if return_address == 0 {
+            return None;
+        }
+
+        // callers live further up the same stack
+        self.rbp = if caller_rbp > self.rbp && caller_rbp - self.rbp < MAX_FRAME_SIZE {
+            caller_rbp
+        } else {
+            0
+        };
+        self.depth += 1;
+        Some(return_address)
+    }
+}
+
+/// Writes the symbolized call stack starting at the frame `rbp` to `out`.
+pub fn write_backtrace(out: &mut impl fmt::Write, rbp: usize) -> fmt::Result {
+    writeln!(out, "backtrace:")?;
+    for (index, address) in frames(rbp).enumerate() {
+        // look up the call instruction, not the one after it
+        writeln!(out, "  {:>2}: {}", index, Symbolized(address - 1))?;
+    }
+    Ok(())
+}
+
+/// Prints the symbolized call stack starting at the frame `rbp` to the screen.
+pub fn print_backtrace(rbp: usize) {
+    let _ = write_backtrace(&mut Screen, rbp);
+}
+
+/// Writes to the screen through `print!`, taking its lock for every piece.
+struct Screen;
+
+impl fmt::Write for Screen {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::print!("{}", s);
+        Ok(())
+    }
+}
+
+/// Formats an address as `<address> <symbol>+<offset>`.
+pub struct Symbolized(pub usize);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.0)?;
+        match lookup(self.0) {
+            Some((name, offset)) => write!(f, " {}+{:#x}", name, offset),
+            None => write!(f, " <unknown>"),
+        }
+    }
+}
+
+/// Returns the name of the symbol containing `address` and the offset of
+/// `address` into it.
+pub fn lookup(address: usize) -> Option<(&'static str, usize)> {
+    let mut best = None;
+    for line in symbol_table().lines() {
+        let mut fields = line.splitn(3, ' ');
+        let start = match fields.next().map(|a| usize::from_str_radix(a, 16)) {
+            Some(Ok(start)) => start,
+            _ => continue,
+        };
+        let name = match fields.nth(1) {
+            Some(name) => name,
+            None => continue,
+        };
+        if start > address {
+            break; // the table is sorted
+        }
+        best = Some((name, address - start));
+    }
+    best
+}
+
+/// Returns the embedded symbol table, empty if none was embedded.
+fn symbol_table() -> &'static str {
+    // The table is patched into the image after compilation, so keep the
+    // compiler from assuming the static's contents.
+    let table: &'static [u8; KSYMS_SIZE] = unsafe { core::ptr::read_volatile(&&KSYMS) };
+    let len = table.iter().position(|&b| b == 0).unwrap_or(KSYMS_SIZE);
+    core::str::from_utf8(&table[..len]).unwrap_or("")
+}
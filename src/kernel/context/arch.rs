@@ -0,0 +1,121 @@
+//! The architecture specific part of a context switch.
+//!
+//! Contexts are switched by `switch_to`, which pushes the callee-saved
+//! registers and the flags onto the stack of the previous context and pops
+//! those of the next one from its stack. The other registers are saved by the
+//! compiler around the call. CR3 is only reloaded if the next context uses
+//! other page tables.
+
+use core::mem;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::PhysAddr;
+
+/// The registers of a context that is not running.
+///
+/// `switch_to` expects `rsp` at offset 0 and `cr3` at offset 8.
+#[derive(Debug)]
+#[repr(C)]
+pub struct Context {
+    /// The stack pointer, pointing to the saved callee-saved registers.
+    rsp: usize,
+    /// The physical address of the level 4 page table.
+    cr3: usize,
+}
+
+impl Context {
+    pub const fn new() -> Self {
+        Context { rsp: 0, cr3: 0 }
+    }
+
+    /// Returns the level 4 page table of the context.
+    pub fn page_table(&self) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(self.cr3 as u64))
+    }
+
+    /// Sets the level 4 page table loaded when switching to the context.
+    pub fn set_page_table(&mut self, frame: PhysFrame) {
+        self.cr3 = frame.start_address().as_u64() as usize;
+    }
+
+    /// Prepares a new context to start in `context_entry` with `func` as its
+    /// argument, on the stack ending at `stack_end`.
+    ///
+    /// This function is unsafe because the caller must guarantee that the
+    /// stack is valid and unused.
+    pub unsafe fn set_entry(&mut self, stack_end: usize, func: fn()) {
+        // `context_entry` must be entered with the stack aligned as after a call
+        let stack_end = stack_end & !0xf;
+        let frame = [
+            0x2,                         // rflags, interrupts disabled
+            0,                           // r15
+            0,                           // r14
+            0,                           // r13
+            func as usize,               // r12, passed on to `context_entry`
+            0,                           // rbx
+            0,                           // rbp, ends backtraces
+            context_trampoline as usize, // return address of `switch_to`
+        ];
+        let rsp = stack_end - mem::size_of_val(&frame);
+        (rsp as *mut [usize; 8]).write(frame);
+        self.rsp = rsp;
+    }
+}
+
+global_asm!(
+    r#"
+.intel_syntax noprefix
+
+# switch_to(prev: *mut Context, next: *const Context)
+.global context_switch_to
+context_switch_to:
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    pushfq
+    mov [rdi], rsp
+    mov rax, [rsi + 8]
+    mov rcx, cr3
+    cmp rax, rcx
+    je 1f
+    mov cr3, rax
+1:
+    mov rsp, [rsi]
+    popfq
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    ret
+
+# the first return address of a new context
+.global context_trampoline
+context_trampoline:
+    mov rdi, r12
+    call context_entry
+    ud2
+
+.att_syntax prefix
+"#
+);
+
+extern "C" {
+    fn context_switch_to(prev: *mut Context, next: *const Context);
+    fn context_trampoline();
+}
+
+/// Saves the registers of the running context to `prev` and continues the
+/// context saved in `next`.
+///
+/// Returns when another context switches back to `prev`.
+///
+/// This function is unsafe because `next` must have been saved by `switch_to`
+/// or prepared by `set_entry`, and both must stay valid during the switch.
+#[inline(never)]
+pub unsafe fn switch_to(prev: *mut Context, next: *const Context) {
+    context_switch_to(prev, next);
+}
@@ -1,5 +1,15 @@
+use super::arch;
+use crate::kernel::memory::address_space::AddressSpace;
+use crate::kernel::process::ProcessId;
+use crate::kernel::syscall::SyscallFrame;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::fmt;
+use spin::Mutex;
+use x86_64::VirtAddr;
+
 /// The status of a context - used for scheduling
-/// See `syscall::process::waitpid` and the `sync` module for examples of usage
+/// See `process::waitpid` and the `sync` module for examples of usage
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Status {
     Runnable,
@@ -8,16 +18,110 @@ pub enum Status {
 }
 
 /// A context, which identifies either a process or a thread
-#[derive(Debug)]
 pub struct Context {
     /// The ID of this context
     pub id: ContextId,
+    /// A name for diagnostics
+    pub name: &'static str,
     /// Status of context
     pub status: Status,
     pub status_reason: &'static str,
     /// Context running or not
     pub running: bool,
+    /// The timer tick at which a sleeping context is unblocked
+    pub wake_at: Option<u64>,
+    /// The CPU this context is running on, or ran on last
+    pub cpu_id: Option<usize>,
+    /// The registers saved while the context is not running
+    pub arch: arch::Context,
+    /// Kernel stack; `None` for the boot context of a CPU, which keeps its
+    /// boot stack
+    pub kstack: Option<Box<[u8]>>,
+    /// The process this context is a thread of; `None` for kernel threads
+    pub process: Option<ProcessId>,
+    /// The user address space; `None` for kernel threads
+    pub addr_space: Option<Arc<Mutex<AddressSpace>>>,
+    /// The registers the context enters user code with
+    pub user_entry: Option<SyscallFrame>,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+impl Context {
+    pub fn new(id: ContextId) -> Self {
+        Context {
+            id,
+            name: "",
+            status: Status::Blocked,
+            status_reason: "new",
+            running: false,
+            wake_at: None,
+            cpu_id: None,
+            arch: arch::Context::new(),
+            kstack: None,
+            process: None,
+            addr_space: None,
+            user_entry: None,
+        }
+    }
+
+    /// Returns the end of the kernel stack, if the context has its own.
+    pub fn kstack_end(&self) -> Option<VirtAddr> {
+        let kstack = self.kstack.as_ref()?;
+        Some(VirtAddr::from_ptr(kstack.as_ptr()) + kstack.len())
+    }
+
+    /// Runs the context in `addr_space` from now on.
+    pub fn set_addr_space(&mut self, addr_space: Arc<Mutex<AddressSpace>>) {
+        self.arch.set_page_table(addr_space.lock().page_table());
+        self.addr_space = Some(addr_space);
+    }
+
+    /// Blocks the context, returning whether it was runnable before.
+    pub fn block(&mut self, reason: &'static str) -> bool {
+        if self.status == Status::Runnable {
+            self.status = Status::Blocked;
+            self.status_reason = reason;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Makes the context runnable again, returning whether it was blocked.
+    pub fn unblock(&mut self) -> bool {
+        if self.status == Status::Blocked {
+            self.status = Status::Runnable;
+            self.status_reason = "";
+            self.wake_at = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("status", &self.status)
+            .field("status_reason", &self.status_reason)
+            .field("running", &self.running)
+            .field("cpu_id", &self.cpu_id)
+            .field("process", &self.process)
+            .finish()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct ContextId(usize);
+
+impl ContextId {
+    pub const fn new(id: usize) -> Self {
+        ContextId(id)
+    }
+
+    pub fn as_usize(&self) -> usize {
+        self.0
+    }
+}
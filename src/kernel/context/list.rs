@@ -0,0 +1,127 @@
+use super::context::{Context, ContextId, Status};
+use crate::kernel::allocator::slab::{ObjectCache, SlabBox};
+use crate::kernel::{memory, percpu};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use spin::RwLock;
+
+/// The size of the kernel stack of every spawned context.
+pub const KSTACK_SIZE: usize = 64 * 1024;
+
+/// The highest context ID handed out.
+const MAX_CONTEXTS: usize = (isize::max_value() as usize) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextError {
+    /// All context IDs are in use.
+    TooManyContexts,
+}
+
+/// The cache all contexts are allocated from.
+static CONTEXTS: ObjectCache<Context> = ObjectCache::new("context");
+
+/// A context as kept in the table.
+pub type ContextLock = RwLock<SlabBox<Context>>;
+
+/// The table of all contexts, by ID.
+pub struct ContextList {
+    map: BTreeMap<ContextId, Arc<ContextLock>>,
+    next_id: usize,
+}
+
+impl ContextList {
+    pub fn new() -> Self {
+        ContextList {
+            map: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Returns the context with the given ID.
+    pub fn get(&self, id: ContextId) -> Option<&Arc<ContextLock>> {
+        self.map.get(&id)
+    }
+
+    /// Returns the context running on the executing CPU.
+    pub fn current(&self) -> Option<&Arc<ContextLock>> {
+        self.map.get(&super::context_id()?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ContextId, &Arc<ContextLock>)> {
+        self.map.iter()
+    }
+
+    /// Adds a blocked context with a fresh ID to the table.
+    pub fn new_context(&mut self) -> Result<&Arc<ContextLock>, ContextError> {
+        if self.next_id >= MAX_CONTEXTS {
+            self.next_id = 1;
+        }
+        while self.map.contains_key(&ContextId::new(self.next_id)) {
+            self.next_id += 1;
+            if self.next_id >= MAX_CONTEXTS {
+                return Err(ContextError::TooManyContexts);
+            }
+        }
+
+        let id = ContextId::new(self.next_id);
+        self.next_id += 1;
+
+        let context = Arc::new(RwLock::new(CONTEXTS.alloc(Context::new(id))));
+        Ok(self.map.entry(id).or_insert(context))
+    }
+
+    /// Adds a kernel thread running `func` on its own stack and queues it on
+    /// the executing CPU.
+    pub fn spawn(
+        &mut self,
+        name: &'static str,
+        func: fn(),
+    ) -> Result<&Arc<ContextLock>, ContextError> {
+        let count = self.map.len() + 1;
+        let context_lock = self.new_context()?;
+        {
+            let mut context = context_lock.write();
+            let kstack = vec![0u8; KSTACK_SIZE].into_boxed_slice();
+            let stack_end = kstack.as_ptr() as usize + kstack.len();
+            unsafe { context.arch.set_entry(stack_end, func) };
+            context.arch.set_page_table(memory::kernel_page_table());
+            context.kstack = Some(kstack);
+            context.name = name;
+            context.status = Status::Runnable;
+            context.status_reason = "";
+
+            // the scheduler must not allocate, so make room for every context
+            let mut queue = percpu::current().run_queue.lock();
+            let additional = count.saturating_sub(queue.len());
+            queue.reserve(additional);
+            queue.push_back(context.id);
+        }
+        Ok(context_lock)
+    }
+
+    /// Removes a context from the table, freeing its kernel stack once the
+    /// last reference is gone.
+    ///
+    /// Running contexts can not be removed.
+    pub fn remove(&mut self, id: ContextId) -> Option<Arc<ContextLock>> {
+        if self.map.get(&id)?.read().running {
+            return None;
+        }
+        self.map.remove(&id)
+    }
+}
+
+impl Default for ContextList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,18 +1,258 @@
 //! # Context management
 //!
 //! For resources on contexts, please consult [wikipedia](https://en.wikipedia.org/wiki/Context_switch) and  [osdev](https://wiki.osdev.org/Context_Switching)
+//!
+//! Every CPU runs the contexts queued in its per-CPU run queue round-robin.
+//! The timer interrupt preempts the running context, which may also give up
+//! the CPU with `yield_now`, `block` or `exit`.
 
+mod arch;
 mod context;
+mod list;
+mod switch;
+
+pub use context::{Context, ContextId, Status};
+pub use list::{ContextError, ContextList, ContextLock, KSTACK_SIZE};
+pub use switch::{request_switch, switch, switch_if_requested};
+
+use crate::kernel::memory::address_space::AddressSpace;
+use crate::kernel::process::ProcessId;
+use crate::kernel::syscall::SyscallFrame;
+use crate::kernel::{memory, percpu, usermode};
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use x86_64::instructions::interrupts;
 
+lazy_static! {
+    /// Contexts list
+    static ref CONTEXTS: RwLock<ContextList> = RwLock::new(ContextList::new());
+}
+
+/// Creates the context of the code running on the executing CPU.
+///
+/// Must be called once per CPU, after `percpu::init`.
 pub fn init() {
-    // let mut contexts = contexts_mut();
-    // let context_lock = contexts
-    //     .new_context()
-    //     .expect("could not initialize first context");
-
-    // let mut context = context_lock.write();
-    // context.status = Status::Runnable;
-    // context.running = true;
-    // context.cpu_id = Some(crate::cpu_id());
-    // CONTEXT_ID.store(context.id, Ordering::SeqCst);
+    let mut contexts = contexts_mut();
+    let context_lock = contexts
+        .new_context()
+        .expect("could not initialize first context");
+
+    let mut context = context_lock.write();
+    context.name = "boot";
+    context.status = Status::Runnable;
+    context.running = true;
+    context.cpu_id = Some(crate::cpu_id());
+    context.arch.set_page_table(memory::kernel_page_table());
+    percpu::current().current_context.set(Some(context.id));
+}
+
+/// Get the global context list, const
+///
+/// `spawn` and `reap` lock the list for writing with interrupts disabled, so
+/// it must only be locked with interrupts disabled as well: a reader preempted
+/// while holding it would keep them spinning. Use `current` or `with_current`
+/// to get at the current context.
+pub fn contexts() -> RwLockReadGuard<'static, ContextList> {
+    CONTEXTS.read()
+}
+
+/// Get the global context list, mutable
+pub fn contexts_mut() -> RwLockWriteGuard<'static, ContextList> {
+    CONTEXTS.write()
+}
+
+/// Returns the context running on the executing CPU.
+pub fn current() -> Option<Arc<ContextLock>> {
+    interrupts::without_interrupts(|| contexts().current().cloned())
+}
+
+/// Runs `f` with the context running on the executing CPU, with interrupts
+/// disabled, and returns its result.
+///
+/// Returns `None` if there is no current context.
+pub fn with_current<R>(f: impl FnOnce(&mut Context) -> R) -> Option<R> {
+    interrupts::without_interrupts(|| Some(f(&mut contexts().current()?.write())))
+}
+
+/// Returns the ID of the context running on the executing CPU.
+pub fn context_id() -> Option<ContextId> {
+    percpu::try_current()?.current_context.get()
+}
+
+/// Starts a kernel thread running `func` on the executing CPU.
+///
+/// The thread exits with status 0 when `func` returns.
+pub fn spawn(name: &'static str, func: fn()) -> Result<ContextId, ContextError> {
+    // the scheduler gives up while the list is locked, so keep it short
+    interrupts::without_interrupts(|| {
+        let mut contexts = contexts_mut();
+        let context_lock = contexts.spawn(name, func)?;
+        let id = context_lock.read().id;
+        Ok(id)
+    })
+}
+
+/// Starts a thread of `process` running user code in `addr_space` with the
+/// registers in `entry`.
+pub fn spawn_user(
+    name: &'static str,
+    process: ProcessId,
+    addr_space: Arc<Mutex<AddressSpace>>,
+    entry: SyscallFrame,
+) -> Result<ContextId, ContextError> {
+    interrupts::without_interrupts(|| {
+        let mut contexts = contexts_mut();
+        let mut context = contexts.spawn(name, enter_user)?.write();
+        context.set_addr_space(addr_space);
+        context.process = Some(process);
+        context.user_entry = Some(entry);
+        Ok(context.id)
+    })
+}
+
+/// The kernel side of a context started by `spawn_user`.
+fn enter_user() {
+    let entry = with_current(|context| context.user_entry.clone()).flatten();
+    let entry = entry.expect("no user entry");
+    unsafe { usermode::enter(&entry) };
+}
+
+/// Gives up the CPU to the next runnable context, if there is one.
+pub fn yield_now() {
+    interrupts::without_interrupts(|| unsafe {
+        switch();
+    });
+}
+
+/// Blocks the current context until another one calls `unblock` on it.
+pub fn block(reason: &'static str) {
+    block_current(|context| {
+        context.block(reason);
+    });
+}
+
+/// Blocks the current context until the timer reached `tick`, see
+/// `interrupts::ticks`.
+pub fn sleep_until(tick: u64) {
+    block_current(|context| {
+        if context.block("sleep") {
+            context.wake_at = Some(tick);
+        }
+    });
+}
+
+/// Blocks the current context without giving up the CPU.
+///
+/// The context keeps running until it calls `wait`, so it can publish that it
+/// is blocked, e.g. in a `sync::WaitQueue`, before it stops.
+pub fn mark_blocked(reason: &'static str) {
+    with_current(|context| context.block(reason));
+}
+
+/// Gives up the CPU until the current context is no longer blocked.
+pub fn wait() {
+    let context_lock = match current() {
+        Some(context_lock) => context_lock,
+        None => return,
+    };
+    loop {
+        interrupts::disable();
+        if context_lock.read().status != Status::Blocked {
+            interrupts::enable();
+            return;
+        }
+        // wait for an interrupt if nothing else can run
+        if !unsafe { switch() } {
+            interrupts::enable_interrupts_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Blocks the current context with `f` and waits until it is runnable again.
+fn block_current(f: impl FnOnce(&mut Context)) {
+    with_current(f);
+    wait();
+}
+
+/// Makes a blocked context runnable again and queues it on its CPU.
+///
+/// Returns `false` if the context does not exist or was not blocked.
+pub fn unblock(id: ContextId) -> bool {
+    interrupts::without_interrupts(|| {
+        let contexts = contexts();
+        let mut context = match contexts.get(id) {
+            Some(context_lock) => context_lock.write(),
+            None => return false,
+        };
+        if !context.unblock() {
+            return false;
+        }
+        // a context waiting in `block` notices by itself
+        if !context.running {
+            let cpu = context.cpu_id.and_then(percpu::get);
+            if let Some(cpu) = cpu.or_else(percpu::try_current) {
+                cpu.run_queue.lock().push_back(id);
+            }
+        }
+        true
+    })
+}
+
+/// Exits the current context with `status`.
+///
+/// The context stays in the list with `Status::Exited` until it is removed,
+/// which frees its kernel stack.
+pub fn exit(status: usize) -> ! {
+    let context_lock = current();
+    interrupts::disable();
+    if let Some(context_lock) = context_lock {
+        context_lock.write().status = Status::Exited(status);
+    }
+    loop {
+        unsafe { switch() };
+        // nothing else to run, wait for something to be unblocked
+        interrupts::enable_interrupts_and_hlt();
+        interrupts::disable();
+    }
+}
+
+/// Waits until the context `id` exited and stopped running, removes it from
+/// the list and returns its exit status.
+///
+/// Returns `None` if the context does not exist.
+pub fn reap(id: ContextId) -> Option<usize> {
+    loop {
+        let reaped = interrupts::without_interrupts(|| {
+            let mut contexts = contexts_mut();
+            let context = contexts.get(id)?.read();
+            match context.status {
+                Status::Exited(status) if !context.running => {
+                    drop(context);
+                    Some(Some((status, contexts.remove(id))))
+                }
+                _ => Some(None),
+            }
+        })?;
+        match reaped {
+            // the kernel stack and the address space are freed here, with
+            // interrupts enabled and the list unlocked
+            Some((status, _context)) => return Some(status),
+            None => yield_now(),
+        }
+    }
+}
+
+/// The first function running in a context created by `ContextList::spawn`,
+/// called by `arch::context_trampoline`.
+#[no_mangle]
+extern "C" fn context_entry(func: usize) -> ! {
+    switch::finish();
+    interrupts::enable();
+
+    let func: fn() = unsafe { core::mem::transmute(func) };
+    func();
+    exit(0);
 }
@@ -0,0 +1,146 @@
+use super::arch;
+use super::context::{ContextId, Status};
+use super::list::ContextList;
+use super::CONTEXTS;
+use crate::kernel::devices::gdt;
+use crate::kernel::interrupts::ticks;
+use crate::kernel::memory::address_space;
+use crate::kernel::percpu::{self, PerCpu};
+use crate::per_cpu;
+use alloc::collections::VecDeque;
+use core::sync::atomic::{spin_loop_hint, AtomicBool, Ordering};
+
+/// Held from picking the next context until it runs, see `finish`.
+static CONTEXT_SWITCH_LOCK: AtomicBool = AtomicBool::new(false);
+
+per_cpu! {
+    /// Set by the timer to switch contexts once the interrupt is handled.
+    static SWITCH_REQUESTED: AtomicBool = AtomicBool::new(false);
+}
+
+/// Switches to the next runnable context queued on the executing CPU.
+///
+/// The current context is queued again if it is still runnable. Returns
+/// `false` if there was nothing to switch to, and `true` once the current
+/// context runs again.
+///
+/// The scheduler may interrupt code holding any lock, so it only ever tries to
+/// take them and gives up if one is taken.
+///
+/// This function is unsafe because it must be called with interrupts disabled.
+pub unsafe fn switch() -> bool {
+    let cpu = match percpu::try_current() {
+        Some(cpu) => cpu,
+        None => return false,
+    };
+
+    while CONTEXT_SWITCH_LOCK.compare_and_swap(false, true, Ordering::SeqCst) {
+        spin_loop_hint();
+    }
+
+    match pick_next(cpu) {
+        Some((prev, next)) => {
+            arch::switch_to(prev, next);
+            finish();
+            true
+        }
+        None => {
+            CONTEXT_SWITCH_LOCK.store(false, Ordering::SeqCst);
+            false
+        }
+    }
+}
+
+/// Marks the contexts for the switch and returns where to save the current
+/// context's registers and where to load the next ones from.
+fn pick_next(cpu: &PerCpu) -> Option<(*mut arch::Context, *const arch::Context)> {
+    let contexts = CONTEXTS.try_read()?;
+    let mut queue = cpu.run_queue.try_lock()?;
+    wake_sleepers(&contexts, &mut queue, cpu.cpu_id());
+
+    let prev_lock = contexts.get(cpu.current_context.get()?)?;
+    let mut prev = prev_lock.try_write()?;
+
+    // entries of contexts that exited or run elsewhere are dropped
+    let mut next = loop {
+        let id = queue.pop_front()?;
+        let next_lock = match contexts.get(id) {
+            Some(next_lock) => next_lock,
+            None => continue,
+        };
+        let next = match next_lock.try_write() {
+            Some(next) => next,
+            None => {
+                queue.push_front(id);
+                return None;
+            }
+        };
+        if next.status == Status::Runnable && !next.running {
+            break next;
+        }
+    };
+
+    // `prev` keeps running on its stack until `finish`
+    cpu.switched_from.set(Some(prev.id));
+    next.running = true;
+    next.cpu_id = Some(cpu.cpu_id());
+    cpu.current_context.set(Some(next.id));
+
+    // interrupts, exceptions and system calls in user code continue on the
+    // kernel stack
+    if let Some(kstack_end) = next.kstack_end() {
+        gdt::set_kernel_stack(kstack_end);
+        cpu.kernel_rsp.set(kstack_end.as_u64() as usize);
+    }
+    address_space::sync_kernel_entries(next.arch.page_table());
+
+    // the contexts stay in the table, so the pointers outlive the guards
+    Some((&mut prev.arch as *mut _, &next.arch as *const _))
+}
+
+/// Unblocks the sleeping contexts of the CPU whose time has come.
+fn wake_sleepers(contexts: &ContextList, queue: &mut VecDeque<ContextId>, cpu_id: usize) {
+    let now = ticks();
+    for (_, context_lock) in contexts.iter() {
+        let mut context = match context_lock.try_write() {
+            Some(context) => context,
+            None => continue,
+        };
+        let due = context.wake_at.map_or(false, |tick| tick <= now);
+        if due && context.cpu_id == Some(cpu_id) && context.unblock() && !context.running {
+            queue.push_back(context.id);
+        }
+    }
+}
+
+/// Completes a switch on the next context's stack.
+///
+/// Only now that its stack is no longer in use is the previous context marked
+/// as stopped, so that it can be removed, or queued again to run anywhere.
+pub(super) fn finish() {
+    let cpu = percpu::current();
+    if let Some(id) = cpu.switched_from.take() {
+        if let Some(prev_lock) = CONTEXTS.read().get(id) {
+            let mut prev = prev_lock.write();
+            prev.running = false;
+            if prev.status == Status::Runnable {
+                // does not allocate, room for every context was reserved by `spawn`
+                cpu.run_queue.lock().push_back(id);
+            }
+        }
+    }
+    CONTEXT_SWITCH_LOCK.store(false, Ordering::SeqCst);
+}
+
+/// Asks for a switch once the interrupt being handled returns.
+pub fn request_switch() {
+    SWITCH_REQUESTED.get().store(true, Ordering::Relaxed);
+}
+
+/// Switches contexts if `request_switch` was called, from the end of an
+/// interrupt handler after the end of interrupt was signalled.
+pub fn switch_if_requested() {
+    if SWITCH_REQUESTED.get().swap(false, Ordering::Relaxed) {
+        unsafe { switch() };
+    }
+}
@@ -0,0 +1,114 @@
+//! An I/O APIC, which routes the interrupt lines of devices (global system
+//! interrupts, GSIs) to local APICs.
+
+use x86_64::VirtAddr;
+
+/// Register selecting the register accessed through `IOWIN`.
+const IOREGSEL: u64 = 0x00;
+/// Window to the selected register.
+const IOWIN: u64 = 0x10;
+
+const IOAPICVER: u32 = 0x01;
+const IOREDTBL: u32 = 0x10;
+
+const REDIRECTION_MASKED: u64 = 1 << 16;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+
+pub struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    lines: u32,
+}
+
+// The registers are only accessed through the I/O APIC lock.
+unsafe impl Send for IoApic {}
+
+impl IoApic {
+    /// Creates an I/O APIC handling the GSIs starting at `gsi_base` and masks
+    /// all of its lines.
+    ///
+    /// This function is unsafe because the caller must guarantee that `base`
+    /// maps the registers of an I/O APIC uncached.
+    pub unsafe fn new(base: VirtAddr, gsi_base: u32) -> Self {
+        let mut io_apic = IoApic {
+            base,
+            gsi_base,
+            lines: 0,
+        };
+        io_apic.lines = ((io_apic.read(IOAPICVER) >> 16) & 0xff) + 1;
+        for line in 0..io_apic.lines {
+            io_apic.write_redirection(line, REDIRECTION_MASKED);
+        }
+        io_apic
+    }
+
+    /// Returns whether this I/O APIC handles `gsi`.
+    pub fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.lines
+    }
+
+    /// Routes `gsi` to `vector` on the local APIC with the given APIC ID and
+    /// unmasks it.
+    pub fn route(&mut self, gsi: u32, vector: u8, apic_id: u32, trigger: Trigger) {
+        assert!(
+            self.handles(gsi),
+            "GSI {} not handled by this I/O APIC",
+            gsi
+        );
+
+        let mut entry = u64::from(vector) | (u64::from(apic_id) << 56);
+        if trigger.level {
+            entry |= REDIRECTION_LEVEL;
+        }
+        if trigger.active_low {
+            entry |= REDIRECTION_ACTIVE_LOW;
+        }
+        self.write_redirection(gsi - self.gsi_base, entry);
+    }
+
+    /// Masks `gsi`.
+    pub fn mask(&mut self, gsi: u32) {
+        assert!(
+            self.handles(gsi),
+            "GSI {} not handled by this I/O APIC",
+            gsi
+        );
+        self.write_redirection(gsi - self.gsi_base, REDIRECTION_MASKED);
+    }
+
+    fn write_redirection(&mut self, line: u32, entry: u64) {
+        // the low half holds the mask, so set the destination first
+        self.write(IOREDTBL + 2 * line + 1, (entry >> 32) as u32);
+        self.write(IOREDTBL + 2 * line, entry as u32);
+    }
+
+    fn read(&mut self, reg: u32) -> u32 {
+        unsafe {
+            ((self.base + IOREGSEL).as_mut_ptr::<u32>()).write_volatile(reg);
+            ((self.base + IOWIN).as_ptr::<u32>()).read_volatile()
+        }
+    }
+
+    fn write(&mut self, reg: u32, value: u32) {
+        unsafe {
+            ((self.base + IOREGSEL).as_mut_ptr::<u32>()).write_volatile(reg);
+            ((self.base + IOWIN).as_mut_ptr::<u32>()).write_volatile(value);
+        }
+    }
+}
+
+/// The electrical characteristics of an interrupt line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trigger {
+    pub level: bool,
+    pub active_low: bool,
+}
+
+impl Trigger {
+    /// The characteristics of ISA interrupts: edge triggered and active high.
+    pub const ISA: Trigger = Trigger {
+        level: false,
+        active_low: false,
+    };
+}
@@ -0,0 +1,140 @@
+//! The local APIC of the current CPU, accessed through MMIO in xAPIC mode or
+//! through MSRs in x2APIC mode.
+
+use core::sync::atomic::{spin_loop_hint, AtomicBool, AtomicU64, Ordering};
+use x86_64::registers::model_specific::Msr;
+use x86_64::{PhysAddr, VirtAddr};
+
+/// The `IA32_APIC_BASE` MSR.
+const APIC_BASE_MSR: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ADDRESS: u64 = 0x000f_ffff_f000;
+
+/// The first MSR of the x2APIC register block.
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+pub(super) const ID: u32 = 0x20;
+pub(super) const TASK_PRIORITY: u32 = 0x80;
+pub(super) const EOI: u32 = 0xb0;
+pub(super) const SPURIOUS: u32 = 0xf0;
+pub(super) const ERROR_STATUS: u32 = 0x280;
+pub(super) const ICR_LOW: u32 = 0x300;
+pub(super) const ICR_HIGH: u32 = 0x310;
+pub(super) const LVT_TIMER: u32 = 0x320;
+pub(super) const LVT_LINT0: u32 = 0x350;
+pub(super) const LVT_LINT1: u32 = 0x360;
+pub(super) const LVT_ERROR: u32 = 0x370;
+
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+
+/// The virtual address of the xAPIC registers, zero until `enable` ran.
+static BASE: AtomicU64 = AtomicU64::new(0);
+/// Set if the local APICs run in x2APIC mode.
+static X2APIC: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the CPU has a local APIC and whether it supports x2APIC mode.
+pub fn supported() -> (bool, bool) {
+    let cpuid = unsafe { core::arch::x86_64::__cpuid(1) };
+    (cpuid.edx & (1 << 9) != 0, cpuid.ecx & (1 << 21) != 0)
+}
+
+/// Returns the physical address of the local APIC registers.
+pub fn physical_address() -> PhysAddr {
+    let base = unsafe { Msr::new(APIC_BASE_MSR).read() };
+    PhysAddr::new(base & APIC_BASE_ADDRESS)
+}
+
+/// Enables the local APIC of the current CPU.
+///
+/// `mmio` is the virtual address of the registers, only used in xAPIC mode.
+///
+/// This function is unsafe because the caller must guarantee that `mmio` maps
+/// the registers of the local APIC uncached, and that every CPU uses the same
+/// mode.
+pub unsafe fn enable(mmio: VirtAddr, x2apic: bool, spurious_vector: u8) {
+    let mut msr = Msr::new(APIC_BASE_MSR);
+    let mut base = msr.read() | APIC_BASE_ENABLE;
+    if x2apic {
+        base |= APIC_BASE_X2APIC;
+    }
+    msr.write(base);
+
+    BASE.store(mmio.as_u64(), Ordering::SeqCst);
+    X2APIC.store(x2apic, Ordering::SeqCst);
+
+    // the 8259 is not used, so mask the legacy and unused local interrupts
+    write(LVT_TIMER, LVT_MASKED);
+    write(LVT_LINT0, LVT_MASKED);
+    write(LVT_LINT1, LVT_MASKED);
+    write(LVT_ERROR, LVT_MASKED);
+    write(ERROR_STATUS, 0);
+    write(TASK_PRIORITY, 0);
+    write(SPURIOUS, SPURIOUS_ENABLE | u32::from(spurious_vector));
+}
+
+/// Returns the virtual address of the xAPIC registers passed to `enable`.
+pub(super) fn base() -> VirtAddr {
+    VirtAddr::new(BASE.load(Ordering::Relaxed))
+}
+
+/// Returns whether `enable` ran.
+pub fn is_enabled() -> bool {
+    BASE.load(Ordering::Relaxed) != 0 || X2APIC.load(Ordering::Relaxed)
+}
+
+/// Returns whether the local APICs run in x2APIC mode.
+pub fn is_x2apic() -> bool {
+    X2APIC.load(Ordering::Relaxed)
+}
+
+/// Returns the APIC ID of the current CPU.
+pub fn id() -> u32 {
+    if is_x2apic() {
+        read(ID)
+    } else {
+        read(ID) >> 24
+    }
+}
+
+/// Signals the end of the current interrupt.
+pub fn end_of_interrupt() {
+    write(EOI, 0);
+}
+
+/// Sends an inter-processor interrupt described by the low half of the
+/// interrupt command register to the CPU with the given APIC ID.
+pub fn send_ipi(apic_id: u32, command: u32) {
+    if is_x2apic() {
+        let value = (u64::from(apic_id) << 32) | u64::from(command);
+        unsafe { Msr::new(X2APIC_MSR_BASE + (ICR_LOW >> 4)).write(value) };
+    } else {
+        write(ICR_HIGH, apic_id << 24);
+        write(ICR_LOW, command);
+        // wait until the interrupt was delivered
+        while read(ICR_LOW) & (1 << 12) != 0 {
+            spin_loop_hint();
+        }
+    }
+}
+
+/// Reads the register at offset `reg` of the xAPIC register block.
+pub(super) fn read(reg: u32) -> u32 {
+    if is_x2apic() {
+        unsafe { Msr::new(X2APIC_MSR_BASE + (reg >> 4)).read() as u32 }
+    } else {
+        let addr = BASE.load(Ordering::Relaxed) + u64::from(reg);
+        unsafe { (addr as *const u32).read_volatile() }
+    }
+}
+
+/// Writes the register at offset `reg` of the xAPIC register block.
+pub(super) fn write(reg: u32, value: u32) {
+    if is_x2apic() {
+        unsafe { Msr::new(X2APIC_MSR_BASE + (reg >> 4)).write(u64::from(value)) };
+    } else {
+        let addr = BASE.load(Ordering::Relaxed) + u64::from(reg);
+        unsafe { (addr as *mut u32).write_volatile(value) };
+    }
+}
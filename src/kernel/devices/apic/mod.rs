@@ -0,0 +1,166 @@
+//! # APIC interrupt controllers
+//!
+//! Replaces the 8259 PICs with the local APIC of every CPU and the I/O APICs
+//! of the system. The legacy IRQ lines keep the vectors they had on the PICs,
+//! so handlers registered with `interrupts::irq` keep working.
+
+use crate::kernel::acpi;
+use crate::kernel::interrupts::irq;
+use crate::kernel::memory;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::{PhysAddr, VirtAddr};
+
+pub mod io;
+pub mod local;
+
+use io::{IoApic, Trigger};
+
+/// The vector of spurious interrupts of the local APIC.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+/// The size of the register blocks of local and I/O APICs.
+const REGISTERS_SIZE: u64 = 4096;
+
+static IO_APICS: Mutex<Vec<IoApic>> = Mutex::new(Vec::new());
+
+/// Describes the interrupt controllers of the system, as found in the
+/// ACPI MADT.
+#[derive(Debug, Clone)]
+pub struct ApicInfo {
+    /// The physical address of the local APIC registers.
+    pub local_apic_address: PhysAddr,
+    pub io_apics: Vec<IoApicInfo>,
+    /// Legacy IRQs not connected to the GSI with the same number.
+    pub overrides: Vec<InterruptOverride>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// The first GSI handled by this I/O APIC.
+    pub gsi_base: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    /// The legacy IRQ.
+    pub irq: u8,
+    /// The GSI the IRQ is connected to.
+    pub gsi: u32,
+    pub trigger: Trigger,
+}
+
+impl ApicInfo {
+    /// Returns the configuration described by the ACPI MADT, or that of a
+    /// standard PC if there is none.
+    pub fn detect() -> Self {
+        match acpi::get().and_then(|acpi| acpi.madt.as_ref()) {
+            Some(madt) => madt.apic_info(),
+            None => Self::standard_pc(),
+        }
+    }
+
+    /// Returns the configuration of a standard PC (and of QEMU's machines):
+    /// one I/O APIC, with the timer connected to GSI 2.
+    pub fn standard_pc() -> Self {
+        let mut overrides = Vec::new();
+        overrides.push(InterruptOverride {
+            irq: irq::TIMER,
+            gsi: 2,
+            trigger: Trigger::ISA,
+        });
+        let mut io_apics = Vec::new();
+        io_apics.push(IoApicInfo {
+            id: 0,
+            address: PhysAddr::new(0xfec0_0000),
+            gsi_base: 0,
+        });
+        ApicInfo {
+            local_apic_address: local::physical_address(),
+            io_apics,
+            overrides,
+        }
+    }
+
+    /// Returns the GSI and trigger of the legacy IRQ `irq`.
+    pub fn legacy_irq(&self, irq: u8) -> (u32, Trigger) {
+        match self.overrides.iter().find(|o| o.irq == irq) {
+            Some(o) => (o.gsi, o.trigger),
+            None => (u32::from(irq), Trigger::ISA),
+        }
+    }
+}
+
+/// Disables the 8259 PICs, enables the local APIC of the bootstrap CPU and
+/// routes the legacy IRQs to it.
+///
+/// Must be called once, after the heap was initialized and with interrupts
+/// disabled. Returns `false` and leaves the PICs enabled if there is no APIC.
+pub fn init(info: &ApicInfo) -> bool {
+    let (apic, x2apic) = local::supported();
+    if !apic || info.io_apics.is_empty() {
+        return false;
+    }
+
+    let local_mmio = if x2apic {
+        VirtAddr::zero()
+    } else {
+        match memory::map_mmio(info.local_apic_address, REGISTERS_SIZE) {
+            Some(mmio) => mmio,
+            None => return false,
+        }
+    };
+
+    let mut io_apics = IO_APICS.lock();
+    for io_apic in info.io_apics.iter() {
+        let mmio = memory::map_mmio(io_apic.address, REGISTERS_SIZE)
+            .expect("failed to map I/O APIC registers");
+        io_apics.push(unsafe { IoApic::new(mmio, io_apic.gsi_base) });
+    }
+
+    disable_pics();
+    unsafe { local::enable(local_mmio, x2apic, SPURIOUS_VECTOR) };
+
+    let bsp = local::id();
+    for line in 0..irq::IRQ_LINES as u8 {
+        let (gsi, trigger) = info.legacy_irq(line);
+        if let Some(io_apic) = io_apics.iter_mut().find(|io_apic| io_apic.handles(gsi)) {
+            io_apic.route(gsi, irq::IRQ_BASE + line, bsp, trigger);
+        }
+    }
+    true
+}
+
+/// Enables the local APIC of an application processor in the mode `init`
+/// chose for the bootstrap processor.
+pub fn init_ap() {
+    unsafe { local::enable(local::base(), local::is_x2apic(), SPURIOUS_VECTOR) };
+}
+
+/// Masks all lines of the 8259 PICs.
+///
+/// They were remapped by `interrupts::PICS` before, so spurious interrupts
+/// they still raise do not collide with exceptions.
+fn disable_pics() {
+    unsafe {
+        Port::<u8>::new(0x21).write(0xff);
+        Port::<u8>::new(0xa1).write(0xff);
+    }
+}
+
+/// Returns whether the APICs replaced the PICs.
+pub fn is_enabled() -> bool {
+    local::is_enabled()
+}
+
+/// Signals the end of the current interrupt to the local APIC.
+pub fn end_of_interrupt() {
+    local::end_of_interrupt();
+}
+
+/// Spurious interrupts must not be acknowledged.
+pub extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: &mut InterruptStackFrame) {}
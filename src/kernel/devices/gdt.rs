@@ -1,52 +1,145 @@
+//! # Global descriptor table
+//!
+//! The segments are laid out as `SYSCALL` and `SYSRET` expect them: kernel
+//! code and data, then user data and code. Every CPU has its own GDT and TSS;
+//! the TSS holds the stack the CPU switches to when user code is interrupted,
+//! see `set_kernel_stack`, and the interrupt stacks of the exceptions that may
+//! arrive while the stack pointer can not be trusted.
+
+use crate::per_cpu;
+use alloc::boxed::Box;
+use alloc::vec;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
 use lazy_static::lazy_static;
-use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::gdt::{
+    Descriptor, DescriptorFlags, GlobalDescriptorTable, SegmentSelector,
+};
 use x86_64::structures::tss::TaskStateSegment;
-use x86_64::VirtAddr;
+use x86_64::{PrivilegeLevel, VirtAddr};
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+/// NMIs, machine checks and debug exceptions may arrive right after `syscall`
+/// or right before `sysretq`, while the user's stack is loaded.
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+pub const DEBUG_IST_INDEX: u16 = 3;
+
+/// The number of interrupt stacks, one per `*_IST_INDEX`.
+const IST_COUNT: usize = 4;
+/// The size of each interrupt stack.
+const IST_STACK_SIZE: usize = 4096 * 4;
+
+pub const KERNEL_CODE_SELECTOR: SegmentSelector = SegmentSelector::new(1, PrivilegeLevel::Ring0);
+pub const KERNEL_DATA_SELECTOR: SegmentSelector = SegmentSelector::new(2, PrivilegeLevel::Ring0);
+pub const USER_DATA_SELECTOR: SegmentSelector = SegmentSelector::new(3, PrivilegeLevel::Ring3);
+pub const USER_CODE_SELECTOR: SegmentSelector = SegmentSelector::new(4, PrivilegeLevel::Ring3);
+
+/// A TSS the CPU may read while the kernel changes it.
+struct TssCell(UnsafeCell<TaskStateSegment>);
+
+// Only the owning CPU changes its TSS, see `set_kernel_stack`.
+unsafe impl Sync for TssCell {}
 
 lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
+    static ref TSS: TssCell = {
+        static mut STACKS: [u8; IST_COUNT * IST_STACK_SIZE] = [0; IST_COUNT * IST_STACK_SIZE];
+
+        TssCell(UnsafeCell::new(new_tss(VirtAddr::from_ptr(unsafe {
+            &STACKS
+        }))))
     };
 }
 
 lazy_static! {
-    static ref GDT: (GlobalDescriptorTable, Selectors) = {
-        let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (
-            gdt,
-            Selectors {
-                code_selector,
-                tss_selector,
-            },
-        )
-    };
+    static ref GDT: (GlobalDescriptorTable, Selectors) = new_gdt(TSS.0.get());
+}
+
+per_cpu! {
+    /// The TSS loaded by each CPU.
+    static CPU_TSS: AtomicPtr<TaskStateSegment> = AtomicPtr::new(ptr::null_mut());
 }
 
 struct Selectors {
-    code_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
 
-pub fn init() {
-    use x86_64::instructions::segmentation::set_cs;
+/// Creates a TSS whose interrupt stacks follow each other from `stacks_start`.
+fn new_tss(stacks_start: VirtAddr) -> TaskStateSegment {
+    let mut tss = TaskStateSegment::new();
+    for index in 0..IST_COUNT {
+        let stack_end = stacks_start + (index + 1) * IST_STACK_SIZE;
+        tss.interrupt_stack_table[index] = stack_end;
+    }
+    tss
+}
+
+fn new_gdt(tss: *mut TaskStateSegment) -> (GlobalDescriptorTable, Selectors) {
+    let kernel_data =
+        DescriptorFlags::USER_SEGMENT | DescriptorFlags::PRESENT | DescriptorFlags::WRITABLE;
+
+    let mut gdt = GlobalDescriptorTable::new();
+    let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+    let kernel_data = gdt.add_entry(Descriptor::UserSegment(kernel_data.bits()));
+    let user_data = gdt.add_entry(Descriptor::user_data_segment());
+    let user_code = gdt.add_entry(Descriptor::user_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*tss }));
+    debug_assert_eq!(kernel_code, KERNEL_CODE_SELECTOR);
+    debug_assert_eq!(kernel_data, KERNEL_DATA_SELECTOR);
+    debug_assert_eq!(user_data, USER_DATA_SELECTOR);
+    debug_assert_eq!(user_code, USER_CODE_SELECTOR);
+    (gdt, Selectors { tss_selector })
+}
+
+fn load(
+    gdt: &'static (GlobalDescriptorTable, Selectors),
+    tss: *mut TaskStateSegment,
+    cpu_id: usize,
+) {
+    use x86_64::instructions::segmentation::{load_ds, load_es, load_ss, set_cs};
     use x86_64::instructions::tables::load_tss;
 
-    GDT.0.load();
+    gdt.0.load();
+    unsafe {
+        set_cs(KERNEL_CODE_SELECTOR);
+        load_ss(KERNEL_DATA_SELECTOR);
+        load_ds(KERNEL_DATA_SELECTOR);
+        load_es(KERNEL_DATA_SELECTOR);
+        load_tss(gdt.1.tss_selector);
+    }
+    CPU_TSS.get_for(cpu_id).store(tss, Ordering::SeqCst);
+}
+
+/// Loads the GDT and TSS of the bootstrap processor.
+pub fn init() {
+    // runs before the per-CPU data exists
+    load(&GDT, TSS.0.get(), 0);
+}
+
+/// Creates and loads a GDT and TSS for an application processor.
+///
+/// Every CPU needs its own TSS, since it holds the CPU's interrupt stacks and
+/// is marked busy while loaded.
+pub fn init_ap() {
+    let stacks = Box::leak(vec![0u8; IST_COUNT * IST_STACK_SIZE].into_boxed_slice());
+    let tss = Box::leak(Box::new(new_tss(VirtAddr::from_ptr(stacks.as_ptr()))));
+    let tss: *mut TaskStateSegment = tss;
+    load(
+        Box::leak(Box::new(new_gdt(tss))),
+        tss,
+        crate::kernel::percpu::cpu_id(),
+    );
+}
+
+/// Sets the stack the executing CPU switches to when an interrupt or exception
+/// arrives in user code.
+pub fn set_kernel_stack(stack_end: VirtAddr) {
+    let tss = CPU_TSS.get().load(Ordering::SeqCst);
+    assert!(!tss.is_null(), "GDT not loaded");
+    // the TSS is packed, `rsp0` is the unaligned field at offset 4
     unsafe {
-        set_cs(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+        let rsp0 = (tss as *mut u8).add(4) as *mut u64;
+        rsp0.write_unaligned(stack_end.as_u64());
     }
 }
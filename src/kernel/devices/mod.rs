@@ -1,3 +1,4 @@
+pub mod apic;
 pub mod gdt;
 pub mod serial;
 pub mod vga;
@@ -30,7 +30,7 @@ pub fn _print(args: ::core::fmt::Arguments) {
 #[macro_export]
 macro_rules! serial_print {
     ($($arg:tt)*) => {
-        $crate::serial::_print(format_args!($($arg)*));
+        $crate::kernel::devices::serial::_print(format_args!($($arg)*));
     };
 }
 
@@ -0,0 +1,171 @@
+//! Parsing of ELF64 executables for x86_64.
+//!
+//! Only statically linked executables (`ET_EXEC`) are supported; images asking
+//! for an interpreter are rejected.
+
+use super::ExecError;
+use core::convert::TryInto;
+
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const VERSION_CURRENT: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const MACHINE_X86_64: u16 = 0x3e;
+
+const HEADER_SIZE: usize = 64;
+/// The size of a program header.
+pub const PROGRAM_HEADER_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PT_INTERP: u32 = 3;
+
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+/// A validated ELF image.
+#[derive(Debug)]
+pub struct Elf<'a> {
+    image: &'a [u8],
+    /// The address of the first instruction.
+    pub entry: u64,
+    /// The file offset of the program headers.
+    pub phoff: u64,
+    /// The number of program headers.
+    pub phnum: u16,
+}
+
+/// A program header.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    pub fn is_load(&self) -> bool {
+        self.kind == PT_LOAD
+    }
+
+    pub fn readable(&self) -> bool {
+        self.flags & PF_R != 0
+    }
+
+    pub fn writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+
+    pub fn executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+}
+
+impl<'a> Elf<'a> {
+    /// Checks that `image` is an ELF64 executable for x86_64 and that its
+    /// program headers and segments are within the image.
+    pub fn parse(image: &'a [u8]) -> Result<Self, ExecError> {
+        if image.len() < HEADER_SIZE || image[..4] != MAGIC {
+            return Err(ExecError::BadImage("not an ELF file"));
+        }
+        if image[4] != CLASS_64 || image[5] != DATA_LITTLE_ENDIAN {
+            return Err(ExecError::BadImage("not a little-endian ELF64 file"));
+        }
+        if image[6] != VERSION_CURRENT {
+            return Err(ExecError::BadImage("unknown ELF version"));
+        }
+        if read_u16(image, 16) != TYPE_EXEC {
+            return Err(ExecError::BadImage("not an executable"));
+        }
+        if read_u16(image, 18) != MACHINE_X86_64 {
+            return Err(ExecError::BadImage("not an x86_64 executable"));
+        }
+        if usize::from(read_u16(image, 54)) != PROGRAM_HEADER_SIZE {
+            return Err(ExecError::BadImage("unexpected program header size"));
+        }
+
+        let elf = Elf {
+            image,
+            entry: read_u64(image, 24),
+            phoff: read_u64(image, 32),
+            phnum: read_u16(image, 56),
+        };
+        let table_size = u64::from(elf.phnum) * PROGRAM_HEADER_SIZE as u64;
+        if !elf.contains(elf.phoff, table_size) {
+            return Err(ExecError::BadImage("program headers outside of the image"));
+        }
+
+        for header in elf.program_headers() {
+            if header.kind == PT_INTERP {
+                return Err(ExecError::BadImage("dynamically linked"));
+            }
+            if !header.is_load() {
+                continue;
+            }
+            if header.filesz > header.memsz || !elf.contains(header.offset, header.filesz) {
+                return Err(ExecError::BadImage("segment outside of the image"));
+            }
+        }
+        Ok(elf)
+    }
+
+    /// Returns the program headers.
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let image = self.image;
+        let phoff = self.phoff as usize;
+        (0..usize::from(self.phnum)).map(move |index| {
+            let header = &image[phoff + index * PROGRAM_HEADER_SIZE..];
+            ProgramHeader {
+                kind: read_u32(header, 0),
+                flags: read_u32(header, 4),
+                offset: read_u64(header, 8),
+                vaddr: read_u64(header, 16),
+                filesz: read_u64(header, 32),
+                memsz: read_u64(header, 40),
+                align: read_u64(header, 48),
+            }
+        })
+    }
+
+    /// Returns the bytes of a segment stored in the image.
+    pub fn segment_data(&self, header: &ProgramHeader) -> &'a [u8] {
+        let start = header.offset as usize;
+        &self.image[start..start + header.filesz as usize]
+    }
+
+    /// Returns the address the program headers are loaded at, if they are
+    /// part of a loaded segment.
+    pub fn program_headers_address(&self) -> Option<u64> {
+        let table_size = u64::from(self.phnum) * PROGRAM_HEADER_SIZE as u64;
+        self.program_headers()
+            .filter(|header| header.is_load())
+            .find(|header| {
+                header.offset <= self.phoff
+                    && self.phoff + table_size <= header.offset + header.filesz
+            })
+            .map(|header| header.vaddr + (self.phoff - header.offset))
+    }
+
+    fn contains(&self, offset: u64, len: u64) -> bool {
+        offset
+            .checked_add(len)
+            .map_or(false, |end| end <= self.image.len() as u64)
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
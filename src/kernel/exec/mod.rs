@@ -0,0 +1,190 @@
+//! # Program execution
+//!
+//! Loads ELF64 executables into a new user address space and starts them in a
+//! new process. The images are passed in memory, since there is no file
+//! system yet.
+
+mod elf;
+mod stack;
+
+pub use elf::{Elf, ProgramHeader};
+
+use crate::kernel::memory::address_space::{AddressSpace, MapError, USER_END, USER_START};
+use crate::kernel::memory::vma::{Vma, VmaKind};
+use crate::kernel::process::{self, ProcessError, ProcessId};
+use alloc::vec;
+use stack::{AT_ENTRY, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM};
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+/// The size of the stack of a new program.
+pub const USER_STACK_SIZE: u64 = 64 * 1024;
+/// The size the stack of a program can grow to.
+pub const USER_STACK_LIMIT: u64 = 8 * 1024 * 1024;
+/// The end of the stack of a new program, at the top of user space.
+pub const USER_STACK_END: u64 = USER_END;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecError {
+    /// The image is not a supported executable.
+    BadImage(&'static str),
+    /// A segment is outside of user space or overlaps the stack.
+    BadSegment,
+    /// The arguments and environment do not fit on the stack.
+    TooBig,
+    OutOfMemory,
+    TooManyProcesses,
+    TooManyContexts,
+}
+
+impl From<MapError> for ExecError {
+    fn from(err: MapError) -> Self {
+        match err {
+            MapError::OutOfMemory => ExecError::OutOfMemory,
+            _ => ExecError::BadSegment,
+        }
+    }
+}
+
+impl From<ProcessError> for ExecError {
+    fn from(err: ProcessError) -> Self {
+        match err {
+            ProcessError::TooManyProcesses => ExecError::TooManyProcesses,
+            ProcessError::TooManyContexts => ExecError::TooManyContexts,
+        }
+    }
+}
+
+/// A program loaded into its address space, ready to start.
+#[derive(Debug)]
+pub struct Image {
+    pub addr_space: AddressSpace,
+    /// The address of the first instruction.
+    pub entry: VirtAddr,
+    /// The initial stack pointer, pointing to `argc`.
+    pub stack_pointer: VirtAddr,
+}
+
+/// Loads the executable `image` into a new address space, with a stack
+/// holding `args`, `env` and the auxiliary vector.
+pub fn load(image: &[u8], args: &[&str], env: &[&str]) -> Result<Image, ExecError> {
+    let elf = Elf::parse(image)?;
+    let mut addr_space = AddressSpace::new().ok_or(ExecError::OutOfMemory)?;
+    let stack_start = USER_STACK_END - USER_STACK_SIZE;
+    let stack_limit = USER_STACK_END - USER_STACK_LIMIT;
+
+    let mut entry_in_code = false;
+    for header in elf.program_headers().filter(ProgramHeader::is_load) {
+        load_segment(&mut addr_space, &elf, &header, stack_limit)?;
+        let end = header.vaddr + header.memsz;
+        entry_in_code |= header.executable() && header.vaddr <= elf.entry && elf.entry < end;
+    }
+    if !entry_in_code {
+        return Err(ExecError::BadImage("entry point outside of the code"));
+    }
+
+    // the stack pages are mapped when touched
+    addr_space.add_vma(Vma::new(
+        VirtAddr::new(stack_start),
+        VirtAddr::new(USER_STACK_END),
+        PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        VmaKind::Stack {
+            limit: VirtAddr::new(stack_limit),
+        },
+    ))?;
+
+    let mut auxv = vec![
+        (AT_PHENT, elf::PROGRAM_HEADER_SIZE as u64),
+        (AT_PHNUM, u64::from(elf.phnum)),
+        (AT_PAGESZ, Page::<Size4KiB>::SIZE),
+        (AT_ENTRY, elf.entry),
+    ];
+    if let Some(address) = elf.program_headers_address() {
+        auxv.push((AT_PHDR, address));
+    }
+    let stack_pointer = stack::build(
+        &mut addr_space,
+        VirtAddr::new(stack_start),
+        VirtAddr::new(USER_STACK_END),
+        args,
+        env,
+        &auxv,
+    )?;
+
+    Ok(Image {
+        addr_space,
+        entry: VirtAddr::new(elf.entry),
+        stack_pointer,
+    })
+}
+
+/// Maps the pages of a `PT_LOAD` segment below `limit` with the segment's
+/// permissions, adds an area for them and copies the segment's data.
+fn load_segment(
+    addr_space: &mut AddressSpace,
+    elf: &Elf,
+    header: &ProgramHeader,
+    limit: u64,
+) -> Result<(), ExecError> {
+    if header.memsz == 0 {
+        return Ok(());
+    }
+    let end = header
+        .vaddr
+        .checked_add(header.memsz)
+        .ok_or(ExecError::BadSegment)?;
+    if header.vaddr < USER_START || end > limit {
+        return Err(ExecError::BadSegment);
+    }
+
+    let mut flags = PageTableFlags::empty();
+    if header.writable() {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if !header.executable() {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+
+    let first = Page::<Size4KiB>::containing_address(VirtAddr::new(header.vaddr));
+    let last = Page::containing_address(VirtAddr::new(end - 1));
+    for page in Page::range_inclusive(first, last) {
+        match addr_space.page_flags(page) {
+            // shared with another segment, allow what either of them allows
+            Some(mapped) => {
+                let writable = (mapped | flags) & PageTableFlags::WRITABLE;
+                let no_execute = mapped & flags & PageTableFlags::NO_EXECUTE;
+                addr_space.update_flags(page, writable | no_execute)?;
+            }
+            None => {
+                addr_space.map(page, flags)?;
+            }
+        }
+    }
+
+    // a page shared with the previous segment is in its area already
+    let mut start = first.start_address();
+    if addr_space.vmas().find(start).is_some() {
+        start += Page::<Size4KiB>::SIZE;
+    }
+    let end = last.start_address() + Page::<Size4KiB>::SIZE;
+    if start < end {
+        addr_space.add_vma(Vma::new(start, end, flags, VmaKind::Anonymous))?;
+    }
+
+    // the rest of the segment stays zeroed
+    addr_space.write(VirtAddr::new(header.vaddr), elf.segment_data(header))?;
+    Ok(())
+}
+
+/// Loads the executable `image` and starts it in a new child process of the
+/// current one.
+pub fn spawn(
+    name: &'static str,
+    image: &[u8],
+    args: &[&str],
+    env: &[&str],
+) -> Result<ProcessId, ExecError> {
+    let image = load(image, args, env)?;
+    let id = process::spawn_user(name, image.addr_space, image.entry, image.stack_pointer)?;
+    Ok(id)
+}
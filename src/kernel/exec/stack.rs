@@ -0,0 +1,74 @@
+//! The initial user stack, as the System V ABI describes it.
+//!
+//! From the stack pointer upwards: `argc`, the `argv` pointers and a null
+//! pointer, the `envp` pointers and a null pointer, then the auxiliary vector
+//! of type and value pairs ending with `AT_NULL`. The strings are stored above,
+//! at the end of the stack.
+
+use super::ExecError;
+use crate::kernel::memory::address_space::AddressSpace;
+use alloc::vec::Vec;
+use core::mem;
+use x86_64::VirtAddr;
+
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_ENTRY: u64 = 9;
+
+/// Writes the arguments, environment and auxiliary vector to the mapped
+/// stack `start..end` and returns the initial stack pointer.
+pub fn build(
+    addr_space: &mut AddressSpace,
+    start: VirtAddr,
+    end: VirtAddr,
+    args: &[&str],
+    env: &[&str],
+    auxv: &[(u64, u64)],
+) -> Result<VirtAddr, ExecError> {
+    let mut sp = end.as_u64();
+    let mut push_str = |s: &str| -> Result<u64, ExecError> {
+        let len = s.len() as u64 + 1;
+        if sp - start.as_u64() < len {
+            return Err(ExecError::TooBig);
+        }
+        sp -= len;
+        addr_space.write(VirtAddr::new(sp), s.as_bytes())?;
+        addr_space.write(VirtAddr::new(sp + len - 1), &[0])?;
+        Ok(sp)
+    };
+    let args = args
+        .iter()
+        .map(|arg| push_str(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let env = env
+        .iter()
+        .map(|var| push_str(var))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut words = Vec::new();
+    words.push(args.len() as u64);
+    words.extend_from_slice(&args);
+    words.push(0);
+    words.extend_from_slice(&env);
+    words.push(0);
+    for &(kind, value) in auxv.iter().chain(&[(AT_NULL, 0)]) {
+        words.push(kind);
+        words.push(value);
+    }
+
+    // the stack pointer must be 16-byte aligned at `argc`
+    let size = (words.len() * mem::size_of::<u64>()) as u64;
+    let sp = (sp - size.min(sp)) & !0xf;
+    if sp < start.as_u64() {
+        return Err(ExecError::TooBig);
+    }
+    let mut bytes = Vec::with_capacity(size as usize);
+    for word in words {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    addr_space.write(VirtAddr::new(sp), &bytes)?;
+    Ok(VirtAddr::new(sp))
+}
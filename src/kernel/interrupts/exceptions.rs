@@ -0,0 +1,503 @@
+//! # CPU exception handlers
+//!
+//! Handlers for the architectural exceptions (vectors 0-31). Vectors 15, 21-29
+//! and 31 are reserved and never raised by the CPUs we target, so the `x86_64`
+//! crate does not let us set them.
+//!
+//! Every handler prints the exception, its decoded error code and the CPU state
+//! it has access to. Breakpoints, debug exceptions and NMIs resume the
+//! interrupted code; all other exceptions are fatal when raised by kernel code,
+//! and terminate the process when raised by user code. Page faults on pages
+//! that are not mapped yet or copy-on-write are resolved and the access is
+//! retried.
+//!
+//! Every handler switches to the kernel's GS base first, so that the per-CPU
+//! data is available even if the exception was raised by user code.
+
+use crate::kernel::backtrace::{self, Symbolized};
+use crate::kernel::context;
+use crate::kernel::devices::{gdt, serial::SERIAL1, vga::WRITER};
+use crate::kernel::memory::address_space;
+use crate::kernel::percpu::KernelGsGuard;
+use crate::kernel::process;
+use crate::{print, println, serial_print, serial_println};
+use core::fmt::{self, Write};
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+/// Prints to the screen and the serial port.
+macro_rules! report {
+    ($($arg:tt)*) => {{
+        println!($($arg)*);
+        serial_println!($($arg)*);
+    }};
+}
+
+pub fn init(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    unsafe {
+        idt.debug
+            .set_handler_fn(debug_handler)
+            .set_stack_index(gdt::DEBUG_IST_INDEX);
+        idt.non_maskable_interrupt
+            .set_handler_fn(nmi_handler)
+            .set_stack_index(gdt::NMI_IST_INDEX);
+    }
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available
+        .set_handler_fn(device_not_available_handler);
+    idt.coprocessor_segment_overrun
+        .set_handler_fn(coprocessor_segment_overrun_handler);
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present
+        .set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
+    idt.page_fault.set_handler_fn(page_fault_handler);
+    idt.x87_floating_point
+        .set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    unsafe {
+        idt.machine_check
+            .set_handler_fn(machine_check_handler)
+            .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+    }
+    idt.simd_floating_point
+        .set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+    idt.security_exception
+        .set_handler_fn(security_exception_handler);
+}
+
+/// The privilege level an exception was raised from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Kernel,
+    User,
+}
+
+impl Origin {
+    pub fn of(stack_frame: &InterruptStackFrame) -> Self {
+        if stack_frame.code_segment & 0b11 == 3 {
+            Origin::User
+        } else {
+            Origin::Kernel
+        }
+    }
+}
+
+/// The error code pushed by exceptions related to a segment selector.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectorErrorCode(pub u64);
+
+impl SelectorErrorCode {
+    /// Returns whether the exception was caused by an event external to the program.
+    pub fn external(self) -> bool {
+        self.0 & 0b1 != 0
+    }
+
+    /// Returns the descriptor table the selector index refers to.
+    pub fn table(self) -> &'static str {
+        if self.0 & 0b10 != 0 {
+            "IDT"
+        } else if self.0 & 0b100 != 0 {
+            "LDT"
+        } else {
+            "GDT"
+        }
+    }
+
+    /// Returns the index of the descriptor in its table.
+    pub fn index(self) -> u64 {
+        (self.0 >> 3) & 0x1fff
+    }
+}
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "no selector");
+        }
+        write!(f, "{} index {}", self.table(), self.index())?;
+        if self.external() {
+            write!(f, " (external event)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the exception, its details and the interrupted CPU state to `out`.
+fn print_state(
+    out: &mut dyn fmt::Write,
+    name: &str,
+    stack_frame: &InterruptStackFrame,
+    details: fmt::Arguments,
+) {
+    // there is nowhere to report failures to print
+    let _ = write_state(out, name, stack_frame, details);
+}
+
+fn write_state(
+    out: &mut dyn fmt::Write,
+    name: &str,
+    stack_frame: &InterruptStackFrame,
+    details: fmt::Arguments,
+) -> fmt::Result {
+    writeln!(
+        out,
+        "EXCEPTION: {} in {:?} code",
+        name,
+        Origin::of(stack_frame)
+    )?;
+    writeln!(out, "{}", details)?;
+    writeln!(
+        out,
+        "at {}",
+        Symbolized(stack_frame.instruction_pointer.as_u64() as usize)
+    )?;
+    writeln!(
+        out,
+        "RIP {:#018x}  CS {:#06x}  RFLAGS {:#018x}",
+        stack_frame.instruction_pointer.as_u64(),
+        stack_frame.code_segment,
+        stack_frame.cpu_flags
+    )?;
+    writeln!(
+        out,
+        "RSP {:#018x}  SS {:#06x}",
+        stack_frame.stack_pointer.as_u64(),
+        stack_frame.stack_segment
+    )?;
+    writeln!(out, "CR0 {:?}", Cr0::read())?;
+    writeln!(out, "CR2 {:?}  CR3 {:?}", Cr2::read(), Cr3::read().0)?;
+    writeln!(out, "CR4 {:?}", Cr4::read())
+}
+
+/// Writes to the screen and the serial port, like `report!`.
+struct Report;
+
+impl fmt::Write for Report {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        print!("{}", s);
+        serial_print!("{}", s);
+        Ok(())
+    }
+}
+
+/// Writes to the screen and the serial port only if they are not in use, and
+/// drops the output otherwise.
+///
+/// For handlers that may interrupt code holding their locks on the same CPU,
+/// which `without_interrupts` does not prevent for NMIs and machine checks.
+struct TryReport;
+
+impl fmt::Write for TryReport {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Some(mut writer) = WRITER.try_lock() {
+            let _ = writer.write_str(s);
+        }
+        if let Some(mut serial) = SERIAL1.try_lock() {
+            let _ = serial.write_str(s);
+        }
+        Ok(())
+    }
+}
+
+/// Reports an exception that the interrupted code cannot continue from.
+///
+/// Must be called directly from the exception handler, so that it can find
+/// the interrupted code's frames.
+#[inline(never)]
+fn fault(name: &str, stack_frame: &InterruptStackFrame, details: fmt::Arguments) -> ! {
+    print_state(&mut Report, name, stack_frame, details);
+    match Origin::of(stack_frame) {
+        Origin::Kernel => {
+            // the panic backtrace would start in the handler
+            backtrace::print_backtrace(backtrace::handler_interrupted_frame());
+            panic!("EXCEPTION: {} in kernel code", name)
+        }
+        Origin::User => user_fault(name),
+    }
+}
+
+/// The exit status of a process terminated by an exception in user code.
+pub const FAULT_EXIT_STATUS: usize = usize::max_value();
+
+/// Handles an exception raised by user code by terminating the process.
+fn user_fault(name: &str) -> ! {
+    report!(
+        "terminating process {:?} after {}",
+        process::process_id(),
+        name
+    );
+    // the process list may be locked by a context preempted on this CPU, so
+    // this must not spin with interrupts disabled; user code always runs with
+    // them enabled, like the system calls exiting the process
+    interrupts::enable();
+    process::exit(FAULT_EXIT_STATUS);
+}
+
+extern "x86-interrupt" fn divide_error_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "DIVIDE ERROR",
+        stack_frame,
+        format_args!("division by zero or overflow"),
+    );
+}
+
+extern "x86-interrupt" fn debug_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    print_state(
+        &mut TryReport,
+        "DEBUG",
+        stack_frame,
+        format_args!("resuming"),
+    );
+}
+
+extern "x86-interrupt" fn nmi_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    print_state(
+        &mut TryReport,
+        "NON-MASKABLE INTERRUPT",
+        stack_frame,
+        format_args!("resuming"),
+    );
+}
+
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+}
+
+extern "x86-interrupt" fn overflow_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault("OVERFLOW", stack_frame, format_args!("INTO with OF set"));
+}
+
+extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "BOUND RANGE EXCEEDED",
+        stack_frame,
+        format_args!("BOUND index out of range"),
+    );
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "INVALID OPCODE",
+        stack_frame,
+        format_args!("undefined or reserved opcode"),
+    );
+}
+
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "DEVICE NOT AVAILABLE",
+        stack_frame,
+        format_args!("FPU or SIMD instruction while disabled"),
+    );
+}
+
+extern "x86-interrupt" fn coprocessor_segment_overrun_handler(
+    stack_frame: &mut InterruptStackFrame,
+) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "COPROCESSOR SEGMENT OVERRUN",
+        stack_frame,
+        format_args!("x87 operand outside segment"),
+    );
+}
+
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: &mut InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    let _gs = KernelGsGuard::enter();
+    print_state(
+        &mut Report,
+        "DOUBLE FAULT",
+        stack_frame,
+        format_args!("fault while handling a fault"),
+    );
+    // the handler runs on its own stack, so walk the interrupted one here
+    backtrace::print_backtrace(backtrace::interrupted_frame());
+    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+}
+
+extern "x86-interrupt" fn invalid_tss_handler(
+    stack_frame: &mut InterruptStackFrame,
+    error_code: u64,
+) {
+    let _gs = KernelGsGuard::enter();
+    let selector = SelectorErrorCode(error_code);
+    fault(
+        "INVALID TSS",
+        stack_frame,
+        format_args!("selector: {}", selector),
+    );
+}
+
+extern "x86-interrupt" fn segment_not_present_handler(
+    stack_frame: &mut InterruptStackFrame,
+    error_code: u64,
+) {
+    let _gs = KernelGsGuard::enter();
+    let selector = SelectorErrorCode(error_code);
+    fault(
+        "SEGMENT NOT PRESENT",
+        stack_frame,
+        format_args!("selector: {}", selector),
+    );
+}
+
+extern "x86-interrupt" fn stack_segment_fault_handler(
+    stack_frame: &mut InterruptStackFrame,
+    error_code: u64,
+) {
+    let _gs = KernelGsGuard::enter();
+    let selector = SelectorErrorCode(error_code);
+    fault(
+        "STACK SEGMENT FAULT",
+        stack_frame,
+        format_args!("selector: {}", selector),
+    );
+}
+
+extern "x86-interrupt" fn general_protection_fault_handler(
+    stack_frame: &mut InterruptStackFrame,
+    error_code: u64,
+) {
+    let _gs = KernelGsGuard::enter();
+    let selector = SelectorErrorCode(error_code);
+    fault(
+        "GENERAL PROTECTION FAULT",
+        stack_frame,
+        format_args!("selector: {}", selector),
+    );
+}
+
+extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: &mut InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let _gs = KernelGsGuard::enter();
+    let addr = Cr2::read();
+    // the address space or the frame allocator may be locked by a context that
+    // was preempted on this CPU, so wait for them with interrupts enabled if
+    // the faulting code had them enabled
+    let flags = RFlags::from_bits_truncate(stack_frame.cpu_flags);
+    if flags.contains(RFlags::INTERRUPT_FLAG) {
+        interrupts::enable();
+    }
+    let resolved = resolve_page_fault(addr, error_code);
+    interrupts::disable();
+    if resolved {
+        return;
+    }
+    fault(
+        "PAGE FAULT",
+        stack_frame,
+        format_args!("Accessed Address: {:?}\nError Code: {:?}", addr, error_code),
+    );
+}
+
+/// Resolves a fault on a page of the current address space that is not
+/// mapped yet or copy-on-write, returning whether the access can be retried.
+fn resolve_page_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    let page = Page::containing_address(addr);
+    if !address_space::is_user_page(page) {
+        return false;
+    }
+    let addr_space = context::with_current(|context| context.addr_space.clone()).flatten();
+    let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    let resolved = match addr_space {
+        Some(addr_space) => addr_space.lock().resolve_fault(page, write),
+        None => return false,
+    };
+    match resolved {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            report!("could not map {:?}: {:?}", page, err);
+            false
+        }
+    }
+}
+
+extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "x87 FLOATING POINT",
+        stack_frame,
+        format_args!("unmasked x87 exception"),
+    );
+}
+
+extern "x86-interrupt" fn alignment_check_handler(
+    stack_frame: &mut InterruptStackFrame,
+    _error_code: u64,
+) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "ALIGNMENT CHECK",
+        stack_frame,
+        format_args!("unaligned memory access"),
+    );
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: &mut InterruptStackFrame) -> ! {
+    let _gs = KernelGsGuard::enter();
+    print_state(
+        &mut TryReport,
+        "MACHINE CHECK",
+        stack_frame,
+        format_args!("hardware error"),
+    );
+    panic!("EXCEPTION: MACHINE CHECK");
+}
+
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "SIMD FLOATING POINT",
+        stack_frame,
+        format_args!("unmasked SSE exception"),
+    );
+}
+
+extern "x86-interrupt" fn virtualization_handler(stack_frame: &mut InterruptStackFrame) {
+    let _gs = KernelGsGuard::enter();
+    fault("VIRTUALIZATION", stack_frame, format_args!("EPT violation"));
+}
+
+extern "x86-interrupt" fn security_exception_handler(
+    stack_frame: &mut InterruptStackFrame,
+    error_code: u64,
+) {
+    let _gs = KernelGsGuard::enter();
+    fault(
+        "SECURITY EXCEPTION",
+        stack_frame,
+        format_args!("error code: {:#x}", error_code),
+    );
+}
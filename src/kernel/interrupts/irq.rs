@@ -0,0 +1,214 @@
+//! # Hardware interrupt lines
+//!
+//! The lines are the legacy ISA IRQs, whether they are delivered by the 8259
+//! PICs or by the I/O APIC.
+//!
+//! Drivers claim an IRQ line with `register` (or `register_shared` for lines
+//! shared with other devices) and get their handler called whenever the line
+//! fires. The end of interrupt is signalled to the interrupt controller after
+//! all handlers of the line ran, so handlers only deal with their device.
+//!
+//! Handlers run with interrupts disabled and must neither block nor allocate.
+//! A handler that wants another context to run calls
+//! `context::request_switch`; the switch happens after the end of interrupt.
+
+use super::PICS;
+use crate::kernel::context;
+use crate::kernel::devices::apic;
+use crate::kernel::percpu::{self, KernelGsGuard};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::RwLock;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+/// The number of IRQ lines.
+pub const IRQ_LINES: usize = 16;
+
+/// The interrupt vector of IRQ line 0; the lines use consecutive vectors.
+pub const IRQ_BASE: u8 = super::PIC_1_OFFSET;
+
+/// The line of the programmable interval timer.
+pub const TIMER: u8 = 0;
+/// The line of the PS/2 keyboard.
+pub const KEYBOARD: u8 = 1;
+
+struct Handler {
+    id: u64,
+    name: &'static str,
+    shared: bool,
+    handler: Box<dyn Fn() + Send + Sync>,
+}
+
+const NO_HANDLERS: RwLock<Vec<Handler>> = RwLock::new(Vec::new());
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+static HANDLERS: [RwLock<Vec<Handler>>; IRQ_LINES] = [NO_HANDLERS; IRQ_LINES];
+static COUNTS: [AtomicU64; IRQ_LINES] = [ZERO; IRQ_LINES];
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line does not exist.
+    InvalidLine,
+    /// The line is claimed by a handler that does not share it.
+    Busy,
+}
+
+/// Identifies a registered handler, see `unregister`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqHandle {
+    line: u8,
+    id: u64,
+}
+
+impl IrqHandle {
+    /// Returns the line the handler is registered for.
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+}
+
+/// Claims `line` exclusively for `handler`.
+pub fn register<F>(line: u8, name: &'static str, handler: F) -> Result<IrqHandle, IrqError>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    add_handler(line, name, false, Box::new(handler))
+}
+
+/// Registers `handler` for `line`, which other shared handlers may use as well.
+///
+/// All handlers of a shared line are called whenever it fires, so they must
+/// check whether their device raised the interrupt.
+pub fn register_shared<F>(line: u8, name: &'static str, handler: F) -> Result<IrqHandle, IrqError>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    add_handler(line, name, true, Box::new(handler))
+}
+
+fn add_handler(
+    line: u8,
+    name: &'static str,
+    shared: bool,
+    handler: Box<dyn Fn() + Send + Sync>,
+) -> Result<IrqHandle, IrqError> {
+    let handlers = HANDLERS
+        .get(usize::from(line))
+        .ok_or(IrqError::InvalidLine)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    // the interrupt handler takes the lock as well
+    without_interrupts(|| {
+        let mut handlers = handlers.write();
+        if !handlers.is_empty() && (!shared || handlers.iter().any(|h| !h.shared)) {
+            return Err(IrqError::Busy);
+        }
+        handlers.push(Handler {
+            id,
+            name,
+            shared,
+            handler,
+        });
+        Ok(IrqHandle { line, id })
+    })
+}
+
+/// Removes a handler registered with `register` or `register_shared`.
+pub fn unregister(handle: IrqHandle) {
+    without_interrupts(|| {
+        HANDLERS[usize::from(handle.line)]
+            .write()
+            .retain(|h| h.id != handle.id);
+    });
+}
+
+/// Returns the number of times `line` fired since boot.
+pub fn count(line: u8) -> u64 {
+    COUNTS
+        .get(usize::from(line))
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Writes a line per used IRQ with its count and handlers to `out`.
+pub fn write_summary(out: &mut impl fmt::Write) -> fmt::Result {
+    for line in 0..IRQ_LINES {
+        let count = COUNTS[line].load(Ordering::Relaxed);
+        without_interrupts(|| {
+            let handlers = HANDLERS[line].read();
+            if count == 0 && handlers.is_empty() {
+                return Ok(());
+            }
+            write!(out, "IRQ {:>2}: {:>8}", line, count)?;
+            for handler in handlers.iter() {
+                write!(out, " {}", handler.name)?;
+            }
+            writeln!(out)
+        })?;
+    }
+    Ok(())
+}
+
+/// Calls the handlers of `line` and signals the end of the interrupt.
+fn dispatch(line: u8) {
+    let cpu = percpu::current();
+    cpu.irq_depth.set(cpu.irq_depth.get() + 1);
+
+    COUNTS[usize::from(line)].fetch_add(1, Ordering::Relaxed);
+    for handler in HANDLERS[usize::from(line)].read().iter() {
+        (handler.handler)();
+    }
+    end_of_interrupt(line);
+
+    cpu.irq_depth.set(cpu.irq_depth.get() - 1);
+    if !cpu.in_interrupt() {
+        context::switch_if_requested();
+    }
+}
+
+/// Signals the end of the interrupt of `line` to the active interrupt controller.
+fn end_of_interrupt(line: u8) {
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(IRQ_BASE + line) };
+    }
+}
+
+macro_rules! irq_stubs {
+    ($($line:literal => $stub:ident),* $(,)?) => {
+        $(
+            extern "x86-interrupt" fn $stub(stack_frame: &mut InterruptStackFrame) {
+                let _gs = KernelGsGuard::enter();
+                dispatch($line);
+            }
+        )*
+
+        /// Points the IRQ vectors of `idt` to the dispatcher.
+        pub fn init(idt: &mut InterruptDescriptorTable) {
+            $(idt[usize::from(IRQ_BASE + $line)].set_handler_fn($stub);)*
+        }
+    };
+}
+
+irq_stubs! {
+    0 => irq0,
+    1 => irq1,
+    2 => irq2,
+    3 => irq3,
+    4 => irq4,
+    5 => irq5,
+    6 => irq6,
+    7 => irq7,
+    8 => irq8,
+    9 => irq9,
+    10 => irq10,
+    11 => irq11,
+    12 => irq12,
+    13 => irq13,
+    14 => irq14,
+    15 => irq15,
+}
@@ -0,0 +1,69 @@
+use crate::kernel::context;
+use crate::kernel::devices::apic;
+use crate::print;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use pic8259_simple::ChainedPics;
+use spin;
+use x86_64::structures::idt::InterruptDescriptorTable;
+
+pub mod exceptions;
+pub mod irq;
+
+pub const PIC_1_OFFSET: u8 = 32;
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+/// The approximate rate of the timer interrupt (the PIT's default 18.2 Hz).
+pub const TICKS_PER_SECOND: u64 = 18;
+
+/// Number of timer interrupts since boot.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+pub static PICS: spin::Mutex<ChainedPics> =
+    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        exceptions::init(&mut idt);
+        irq::init(&mut idt);
+        idt[usize::from(apic::SPURIOUS_VECTOR)].set_handler_fn(apic::spurious_interrupt_handler);
+
+        idt
+    };
+}
+
+pub fn init_idt() {
+    IDT.load();
+}
+
+/// Returns the number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Registers the handlers of the devices the kernel always drives.
+///
+/// Must be called once the heap is initialized.
+pub fn init_irqs() {
+    irq::register(irq::TIMER, "timer", timer_interrupt_handler).expect("timer IRQ taken");
+    irq::register(irq::KEYBOARD, "keyboard", keyboard_interrupt_handler)
+        .expect("keyboard IRQ taken");
+}
+
+fn timer_interrupt_handler() {
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::testing::check_timeout(ticks);
+
+    print!(".");
+    // preempt the running context, round-robin
+    context::request_switch();
+}
+
+fn keyboard_interrupt_handler() {
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let scancode: u8 = unsafe { port.read() };
+    crate::task::keyboard::add_scancode(scancode);
+}
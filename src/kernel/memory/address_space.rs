@@ -0,0 +1,625 @@
+//! # User address spaces
+//!
+//! Every process has its own level 4 table. The entries covering
+//! `USER_START..USER_END` map the process's memory; all other entries are
+//! shared with the kernel's table, so the kernel is mapped in every address
+//! space without being accessible to user code.
+//!
+//! The bootloader puts the kernel, its stack and the physical memory mapping
+//! into the first level 4 entries, and the heap and MMIO regions use entries
+//! 136 and 170, so user space gets the last quarter of the lower half.
+//!
+//! The memory user code may use is described by `Vma`s; their pages are
+//! mapped on the first access by `resolve_fault`. `mmap`, `munmap` and
+//! `mprotect` add, remove and change areas at run time.
+//!
+//! `fork` shares the frames of an address space with its copy: writable pages
+//! are mapped read-only and `COPY_ON_WRITE` in both, and the first write to
+//! such a page faults and gives it its own copy of the frame.
+//!
+//! Changes to the page tables only flush the TLB of the executing CPU. This is
+//! enough because processes have a single thread: an address space is only
+//! changed by its own thread, or before any thread runs in it, and it is only
+//! loaded on the CPU running that thread. Every other CPU that ran the thread
+//! loaded another level 4 table since, which flushed its user mappings, so
+//! unmapped frames can be released right away. Threads sharing an address
+//! space will need a TLB shootdown first.
+
+use super::vma::{Vma, VmaKind, VmaList};
+use super::{
+    allocate_frame, deallocate_frame, frame_is_shared, kernel_page_table, phys_to_virt,
+    release_frame, share_frame, virt_to_phys, zero_frame,
+};
+use crate::kernel::allocator::slab::ObjectCache;
+use core::ops::Range;
+use core::ptr::NonNull;
+use core::slice;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::structures::paging::mapper::{MapToError, MapperAllSizes};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// The first address of user space.
+pub const USER_START: u64 = 0x_6000_0000_0000;
+/// The end of user space. The last page below the non-canonical hole is left
+/// out, since returning to it with `sysretq` faults in kernel mode.
+pub const USER_END: u64 = 0x_7fff_ffff_f000;
+
+/// The level 4 entries of user space.
+const USER_ENTRIES: Range<usize> = (USER_START >> 39) as usize..256;
+
+/// Where `mmap` starts to look for free addresses.
+pub const MMAP_START: u64 = 0x_7000_0000_0000;
+
+/// Marks a page that is writable, but shares its frame until written to.
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The page is outside of user space.
+    NotUserMemory,
+    /// There is no physical memory for the page or its page tables.
+    OutOfMemory,
+    /// The page is mapped already.
+    AlreadyMapped,
+    /// The page is not mapped.
+    NotMapped,
+    /// The file backing the page could not be read.
+    ReadFailed,
+    /// The range is empty or not page-aligned.
+    BadRange,
+}
+
+/// The page tables of a user program.
+///
+/// The address space owns the frames mapped in user space and releases them,
+/// along with its page tables, when dropped. Frames shared with other address
+/// spaces are freed once the last of them released them.
+#[derive(Debug)]
+pub struct AddressSpace {
+    pml4: PhysFrame,
+    vmas: VmaList,
+}
+
+impl AddressSpace {
+    /// Creates an address space with only the kernel mapped.
+    ///
+    /// Returns `None` if physical memory ran out.
+    pub fn new() -> Option<Self> {
+        let pml4 = allocate_table()?;
+        unsafe { table_mut(pml4).zero() };
+        let kernel = unsafe { table_mut(kernel_page_table()) };
+        debug_assert!(
+            USER_ENTRIES.all(|index| kernel[index].is_unused()),
+            "the kernel uses user space"
+        );
+        sync_kernel_entries(pml4);
+        Some(AddressSpace {
+            pml4,
+            vmas: VmaList::new(),
+        })
+    }
+
+    /// Returns the frame of the level 4 table, the value for CR3.
+    pub fn page_table(&self) -> PhysFrame {
+        self.pml4
+    }
+
+    /// Returns whether the address space is loaded on the executing CPU.
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.pml4
+    }
+
+    /// Returns the areas of user memory, see `add_vma`.
+    pub fn vmas(&self) -> &VmaList {
+        &self.vmas
+    }
+
+    /// Adds an area of user memory, whose pages are mapped when first touched.
+    pub fn add_vma(&mut self, vma: Vma) -> Result<(), MapError> {
+        let first = Page::containing_address(vma.reserved_start());
+        let last = Page::containing_address(vma.end - 1u64);
+        if !is_user_page(first) || !is_user_page(last) {
+            return Err(MapError::NotUserMemory);
+        }
+        self.vmas.insert(vma)
+    }
+
+    /// Adds an area of `len` bytes, rounded up to whole pages, at `addr` or at
+    /// a free address if `None`, and returns its start.
+    ///
+    /// The pages are mapped with `flags` when first touched.
+    pub fn mmap(
+        &mut self,
+        addr: Option<VirtAddr>,
+        len: u64,
+        flags: PageTableFlags,
+        kind: VmaKind,
+    ) -> Result<VirtAddr, MapError> {
+        let len = page_align(len).ok_or(MapError::NotUserMemory)?;
+        let start = match addr {
+            Some(addr) => {
+                let (start, end) = user_range(addr, len)?;
+                self.vmas
+                    .find_free(start, len, end)
+                    .ok_or(MapError::AlreadyMapped)?
+            }
+            None => self
+                .vmas
+                .find_free(VirtAddr::new(MMAP_START), len, VirtAddr::new(USER_END))
+                .ok_or(MapError::OutOfMemory)?,
+        };
+        self.add_vma(Vma::new(start, start + len, flags, kind))?;
+        Ok(start)
+    }
+
+    /// Removes the `len` bytes from `addr` on, rounded up to whole pages, from
+    /// the areas, and unmaps their pages. Frames that are no longer used are
+    /// returned to the frame allocator.
+    pub fn munmap(&mut self, addr: VirtAddr, len: u64) -> Result<(), MapError> {
+        let len = page_align(len).ok_or(MapError::NotUserMemory)?;
+        let (start, end) = user_range(addr, len)?;
+        for vma in self.vmas.remove(start, end) {
+            let first = Page::containing_address(vma.start);
+            let last = Page::containing_address(vma.end - 1u64);
+            for page in Page::range_inclusive(first, last) {
+                match self.unmap(page) {
+                    Ok(()) | Err(MapError::NotMapped) => (),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the flags of the `len` bytes from `addr` on, rounded up to
+    /// whole pages, to `flags`, for the mapped pages as well as the ones
+    /// mapped later. All of the pages must be in areas.
+    pub fn mprotect(
+        &mut self,
+        addr: VirtAddr,
+        len: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), MapError> {
+        let len = page_align(len).ok_or(MapError::NotUserMemory)?;
+        let (start, end) = user_range(addr, len)?;
+        if !self.vmas.covers(start, end) {
+            return Err(MapError::NotMapped);
+        }
+        self.vmas.protect(start, end, flags);
+
+        let first = Page::containing_address(start);
+        let last = Page::containing_address(end - 1u64);
+        for page in Page::range_inclusive(first, last) {
+            let frame = match self.entry_mut(page) {
+                Some(entry) => PhysFrame::containing_address(entry.addr()),
+                None => continue,
+            };
+            // a shared frame is only written to after copying it
+            let flags = if flags.contains(PageTableFlags::WRITABLE) && frame_is_shared(frame) {
+                (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE
+            } else {
+                flags
+            };
+            self.update_flags(page, flags)?;
+        }
+        Ok(())
+    }
+
+    /// Resolves a fault on `page`, caused by a write if `write` is set.
+    ///
+    /// Maps the page if it is in an area, growing stacks as needed, and
+    /// copies it if it is a write to a `COPY_ON_WRITE` page. Returns whether
+    /// the access can be retried.
+    pub fn resolve_fault(&mut self, page: Page, write: bool) -> Result<bool, MapError> {
+        if self.page_flags(page).is_some() {
+            return if write {
+                self.copy_on_write(page)
+            } else {
+                Ok(false)
+            };
+        }
+
+        let vma = match self.vmas.find_or_grow(page) {
+            Some(vma) => vma,
+            None => return Ok(false),
+        };
+        if write && !vma.flags.contains(PageTableFlags::WRITABLE) {
+            return Ok(false);
+        }
+        let flags = vma.flags;
+        let file = match &vma.kind {
+            VmaKind::File { file, offset } => {
+                Some((file.clone(), offset + (page.start_address() - vma.start)))
+            }
+            _ => None,
+        };
+
+        let frame = self.map(page, flags)?;
+        if let Some((file, offset)) = file {
+            let dest = phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+            let dest = unsafe { slice::from_raw_parts_mut(dest, Page::<Size4KiB>::SIZE as usize) };
+            // past the end of the file the page stays zeroed
+            if file.read_at(offset, dest).is_err() {
+                self.unmap(page)?;
+                return Err(MapError::ReadFailed);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns a mapper for the page tables of the address space.
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let offset = phys_to_virt(PhysAddr::new(0));
+        unsafe { OffsetPageTable::new(table_mut(self.pml4), offset) }
+    }
+
+    /// Translates a user address to the physical address it is mapped to.
+    pub fn translate(&mut self, addr: VirtAddr) -> Option<PhysAddr> {
+        self.mapper().translate_addr(addr)
+    }
+
+    /// Returns the flags `page` is mapped with, or `None` if it is not mapped.
+    ///
+    /// `WRITABLE` and `USER_ACCESSIBLE` are only set if every level of the
+    /// page tables allows them, `NO_EXECUTE` if any level forbids execution.
+    pub fn page_flags(&self, page: Page) -> Option<PageTableFlags> {
+        let mut table = unsafe { table_mut(self.pml4) };
+        let mut allowed = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let mut denied = PageTableFlags::empty();
+        for &index in [page.p4_index(), page.p3_index(), page.p2_index()].iter() {
+            let flags = table[index].flags();
+            if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE)
+            {
+                return None;
+            }
+            allowed &= flags;
+            denied |= flags & PageTableFlags::NO_EXECUTE;
+            table = unsafe { table_mut(PhysFrame::containing_address(table[index].addr())) };
+        }
+
+        let flags = table[page.p1_index()].flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        let inherited = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        Some((flags - inherited) | (flags & allowed) | denied)
+    }
+
+    /// Maps `page` to a newly allocated, zeroed frame and returns the frame.
+    ///
+    /// `PRESENT` and `USER_ACCESSIBLE` are added to `flags`.
+    pub fn map(&mut self, page: Page, flags: PageTableFlags) -> Result<PhysFrame, MapError> {
+        let frame = allocate_frame().ok_or(MapError::OutOfMemory)?;
+        unsafe { zero_frame(frame) };
+        if let Err(err) = self.map_to(page, frame, flags) {
+            unsafe { deallocate_frame(frame) };
+            return Err(err);
+        }
+        Ok(frame)
+    }
+
+    /// Maps `page` to `frame`, which belongs to the address space from then on.
+    ///
+    /// `PRESENT` and `USER_ACCESSIBLE` are added to `flags`.
+    pub fn map_to(
+        &mut self,
+        page: Page,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+    ) -> Result<(), MapError> {
+        if !is_user_page(page) {
+            return Err(MapError::NotUserMemory);
+        }
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        match unsafe { self.mapper().map_to(page, frame, flags, &mut KernelFrames) } {
+            Ok(flush) => flush.flush(),
+            Err(MapToError::FrameAllocationFailed) => return Err(MapError::OutOfMemory),
+            Err(_) => return Err(MapError::AlreadyMapped),
+        }
+        self.allow_user_access(page);
+        Ok(())
+    }
+
+    /// Unmaps `page` and releases its frame.
+    pub fn unmap(&mut self, page: Page) -> Result<(), MapError> {
+        if !is_user_page(page) {
+            return Err(MapError::NotUserMemory);
+        }
+        let (frame, flush) = self.mapper().unmap(page).map_err(|_| MapError::NotMapped)?;
+        flush.flush();
+        unsafe { release_frame(frame) };
+        Ok(())
+    }
+
+    /// Changes the flags of the mapped `page`.
+    ///
+    /// `PRESENT` and `USER_ACCESSIBLE` are added to `flags`.
+    pub fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<(), MapError> {
+        if !is_user_page(page) {
+            return Err(MapError::NotUserMemory);
+        }
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        match unsafe { self.mapper().update_flags(page, flags) } {
+            Ok(flush) => flush.flush(),
+            Err(_) => return Err(MapError::NotMapped),
+        }
+        Ok(())
+    }
+
+    /// Copies `bytes` to the mapped user memory at `addr`, regardless of the
+    /// page permissions and of which address space is active.
+    pub fn write(&mut self, addr: VirtAddr, bytes: &[u8]) -> Result<(), MapError> {
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let addr = addr + offset;
+            let page = Page::<Size4KiB>::containing_address(addr);
+            if !is_user_page(page) {
+                return Err(MapError::NotUserMemory);
+            }
+            // map untouched pages of areas, and do not write through to the
+            // other address spaces
+            self.resolve_fault(page, false)?;
+            self.copy_on_write(page)?;
+            let phys = self.translate(addr).ok_or(MapError::NotMapped)?;
+            let len = (page.start_address() + page.size() - addr) as usize;
+            let len = len.min(bytes.len() - offset);
+            unsafe {
+                let dest = phys_to_virt(phys).as_mut_ptr::<u8>();
+                core::ptr::copy_nonoverlapping(bytes[offset..].as_ptr(), dest, len);
+            }
+            offset += len;
+        }
+        Ok(())
+    }
+
+    /// Creates a copy of the address space, which shares all frames with this
+    /// one until either writes to them.
+    ///
+    /// Writable pages become read-only and `COPY_ON_WRITE` in both address
+    /// spaces, see `copy_on_write`.
+    pub fn fork(&mut self) -> Result<AddressSpace, MapError> {
+        let mut child = AddressSpace::new().ok_or(MapError::OutOfMemory)?;
+        child.vmas = self.vmas.clone();
+        self.for_each_page(&mut |page, entry| {
+            let mut flags = entry.flags();
+            if flags.contains(PageTableFlags::WRITABLE) {
+                flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+                entry.set_flags(flags);
+            }
+            let frame = PhysFrame::containing_address(entry.addr());
+            child.map_to(page, frame, flags)?;
+            share_frame(frame);
+            Ok(())
+        })?;
+        // writes must fault from now on
+        if self.is_active() {
+            tlb::flush_all();
+        }
+        Ok(child)
+    }
+
+    /// Makes `page` writable if it is mapped `COPY_ON_WRITE`, copying its
+    /// frame if it is still shared with another address space.
+    ///
+    /// Returns whether the page was mapped `COPY_ON_WRITE`.
+    pub fn copy_on_write(&mut self, page: Page) -> Result<bool, MapError> {
+        let entry = match self.entry_mut(page) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        let flags = entry.flags();
+        if !flags.contains(COPY_ON_WRITE) {
+            return Ok(false);
+        }
+        let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+        let frame = PhysFrame::containing_address(entry.addr());
+        if frame_is_shared(frame) {
+            let copy = allocate_frame().ok_or(MapError::OutOfMemory)?;
+            unsafe {
+                let src = phys_to_virt(frame.start_address()).as_ptr::<u8>();
+                let dest = phys_to_virt(copy.start_address()).as_mut_ptr::<u8>();
+                core::ptr::copy_nonoverlapping(src, dest, Page::<Size4KiB>::SIZE as usize);
+            }
+            entry.set_addr(copy.start_address(), flags);
+            unsafe { release_frame(frame) };
+        } else {
+            // the other address spaces are gone already
+            entry.set_flags(flags);
+        }
+        tlb::flush(page.start_address());
+        Ok(true)
+    }
+
+    /// Returns the level 1 entry mapping `page`, if its page tables exist.
+    fn entry_mut(&mut self, page: Page) -> Option<&mut PageTableEntry> {
+        let mut table = unsafe { table_mut(self.pml4) };
+        for &index in [page.p4_index(), page.p3_index(), page.p2_index()].iter() {
+            let flags = table[index].flags();
+            if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE)
+            {
+                return None;
+            }
+            table = unsafe { table_mut(PhysFrame::containing_address(table[index].addr())) };
+        }
+        let entry = &mut table[page.p1_index()];
+        if entry.flags().contains(PageTableFlags::PRESENT) {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Calls `f` with every mapped user page and its level 1 entry, until it
+    /// fails.
+    fn for_each_page<F>(&mut self, f: &mut F) -> Result<(), MapError>
+    where
+        F: FnMut(Page, &mut PageTableEntry) -> Result<(), MapError>,
+    {
+        let pml4 = unsafe { table_mut(self.pml4) };
+        for index in USER_ENTRIES {
+            visit_entry(&mut pml4[index], 3, (index as u64) << 39, f)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the page tables leading to `page` accessible to user code; the
+    /// mapper creates them for kernel use only.
+    fn allow_user_access(&mut self, page: Page) {
+        let flags = PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE;
+        let indices = [page.p4_index(), page.p3_index(), page.p2_index()];
+        let mut table = unsafe { table_mut(self.pml4) };
+        for &index in indices.iter() {
+            let entry = &mut table[index];
+            entry.set_flags(entry.flags() | flags);
+            table = unsafe { table_mut(PhysFrame::containing_address(entry.addr())) };
+        }
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        // the tables must not be freed while in use
+        if self.is_active() {
+            unsafe { Cr3::write(kernel_page_table(), Cr3Flags::empty()) };
+        }
+        let pml4 = unsafe { table_mut(self.pml4) };
+        for index in USER_ENTRIES {
+            free_entry(&mut pml4[index], 3);
+        }
+        unsafe { free_table(self.pml4) };
+    }
+}
+
+/// Frees the frame `entry` points to, after the frames of its entries if it is
+/// a page table of the given level. Mapped frames are only released, since
+/// they may be shared.
+fn free_entry(entry: &mut PageTableEntry, level: usize) {
+    if entry.is_unused() {
+        return;
+    }
+    let frame = PhysFrame::containing_address(entry.addr());
+    if level > 0 {
+        let table = unsafe { table_mut(frame) };
+        for entry in table.iter_mut() {
+            free_entry(entry, level - 1);
+        }
+        entry.set_unused();
+        unsafe { free_table(frame) };
+    } else {
+        entry.set_unused();
+        unsafe { release_frame(frame) };
+    }
+}
+
+/// Calls `f` with the pages mapped by the present `entry` of the given level,
+/// which maps the addresses from `start`.
+fn visit_entry<F>(
+    entry: &mut PageTableEntry,
+    level: usize,
+    start: u64,
+    f: &mut F,
+) -> Result<(), MapError>
+where
+    F: FnMut(Page, &mut PageTableEntry) -> Result<(), MapError>,
+{
+    if !entry.flags().contains(PageTableFlags::PRESENT) {
+        return Ok(());
+    }
+    if level == 0 {
+        return f(Page::containing_address(VirtAddr::new(start)), entry);
+    }
+    let table = unsafe { table_mut(PhysFrame::containing_address(entry.addr())) };
+    for (index, entry) in table.iter_mut().enumerate() {
+        let start = start | (index as u64) << (12 + 9 * (level - 1));
+        visit_entry(entry, level - 1, start, f)?;
+    }
+    Ok(())
+}
+
+/// Copies the kernel's level 4 entries into the level 4 table `pml4`.
+///
+/// Lower level tables are shared, so this is only needed when the kernel
+/// created a new level 4 entry, and is done before switching to `pml4`.
+pub fn sync_kernel_entries(pml4: PhysFrame) {
+    let kernel = kernel_page_table();
+    if pml4 == kernel {
+        return;
+    }
+    let (kernel, table) = unsafe { (table_mut(kernel), table_mut(pml4)) };
+    for (index, entry) in kernel.iter().enumerate() {
+        if !USER_ENTRIES.contains(&index) {
+            table[index] = entry.clone();
+        }
+    }
+}
+
+/// Rounds `len` up to whole pages, returning `None` if it does not fit into
+/// user space.
+fn page_align(len: u64) -> Option<u64> {
+    let size = Page::<Size4KiB>::SIZE;
+    let len = len.checked_add(size - 1)? & !(size - 1);
+    if len <= USER_END - USER_START {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+/// Returns the start and end of the `len` bytes from `addr` on, checking that
+/// they are page-aligned and in user space.
+fn user_range(addr: VirtAddr, len: u64) -> Result<(VirtAddr, VirtAddr), MapError> {
+    if len == 0 || !addr.is_aligned(Page::<Size4KiB>::SIZE) {
+        return Err(MapError::BadRange);
+    }
+    let start = addr.as_u64();
+    if start < USER_START || start > USER_END - len {
+        return Err(MapError::NotUserMemory);
+    }
+    Ok((addr, addr + len))
+}
+
+/// Returns whether `page` is in user space.
+pub fn is_user_page(page: Page) -> bool {
+    let addr = page.start_address().as_u64();
+    USER_START <= addr && addr < USER_END
+}
+
+/// Returns the page table in `frame`.
+///
+/// This function is unsafe because the caller must guarantee that `frame`
+/// holds a page table and that no other reference to it is used meanwhile.
+unsafe fn table_mut(frame: PhysFrame) -> &'static mut PageTable {
+    &mut *phys_to_virt(frame.start_address()).as_mut_ptr::<PageTable>()
+}
+
+/// The cache the page tables of all address spaces are allocated from.
+static PAGE_TABLES: ObjectCache<PageTable> = ObjectCache::new("page_table");
+
+/// Allocates an uninitialized page table.
+fn allocate_table() -> Option<PhysFrame> {
+    let table = PAGE_TABLES.allocate_raw()?;
+    let addr = virt_to_phys(VirtAddr::from_ptr(table.as_ptr()));
+    Some(PhysFrame::containing_address(addr))
+}
+
+/// Frees a page table allocated through `allocate_table`.
+///
+/// This function is unsafe because the caller must guarantee that the table
+/// is no longer in use.
+unsafe fn free_table(frame: PhysFrame) {
+    PAGE_TABLES.deallocate_raw(NonNull::from(table_mut(frame)));
+}
+
+/// Allocates page tables for the mapper.
+struct KernelFrames;
+
+unsafe impl FrameAllocator<Size4KiB> for KernelFrames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        allocate_table()
+    }
+}
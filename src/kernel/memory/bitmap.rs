@@ -0,0 +1,212 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::slice;
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = 64;
+
+/// A FrameAllocator that tracks every usable 4 KiB frame with one bit.
+///
+/// A set bit means the frame is in use (or not usable at all). The bitmap
+/// itself lives in the first usable region large enough to hold it and is
+/// accessed through the bootloader's physical memory mapping.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    /// Number of frames covered by the bitmap.
+    frame_count: usize,
+    /// Number of frames marked `Usable` in the memory map.
+    usable_frames: usize,
+    /// Number of frames currently free.
+    free_frames: usize,
+    /// Word index at which the next search starts.
+    next: usize,
+}
+
+// The bitmap is only reachable through this allocator.
+unsafe impl Send for BitmapFrameAllocator {}
+
+impl BitmapFrameAllocator {
+    /// Create a BitmapFrameAllocator from the passed memory map.
+    ///
+    /// This function is unsafe because the caller must guarantee that the passed
+    /// memory map is valid and that all frames marked as `USABLE` in it are really
+    /// unused. The complete physical memory must also be mapped at the passed
+    /// `physical_memory_offset`.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        // the bitmap only needs to reach the end of the last usable region
+        let max_addr = usable_regions()
+            .map(|r| r.range.end_addr())
+            .max()
+            .unwrap_or(0);
+        let frame_count = (max_addr / FRAME_SIZE) as usize;
+        let words = (frame_count + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        let bitmap_bytes = (words * 8) as u64;
+
+        // place the bitmap at the start of the first region that can hold it
+        let bitmap_region = usable_regions()
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_bytes)
+            .expect("no usable region large enough for the frame bitmap");
+        let bitmap_start = bitmap_region.range.start_addr();
+        let bitmap_ptr = (physical_memory_offset + bitmap_start).as_mut_ptr::<u64>();
+
+        let mut allocator = BitmapFrameAllocator {
+            bitmap: slice::from_raw_parts_mut(bitmap_ptr, words),
+            frame_count,
+            usable_frames: 0,
+            free_frames: 0,
+            next: 0,
+        };
+
+        // everything is in use until the memory map says otherwise
+        for word in allocator.bitmap.iter_mut() {
+            *word = !0;
+        }
+        for region in usable_regions() {
+            let start = (region.range.start_addr() / FRAME_SIZE) as usize;
+            let end = (region.range.end_addr() / FRAME_SIZE) as usize;
+            for index in start..end {
+                allocator.clear(index);
+            }
+            allocator.usable_frames += end - start;
+            allocator.free_frames += end - start;
+        }
+
+        // the frames holding the bitmap are no longer available
+        let first = (bitmap_start / FRAME_SIZE) as usize;
+        let last = ((bitmap_start + bitmap_bytes + FRAME_SIZE - 1) / FRAME_SIZE) as usize;
+        for index in first..last {
+            allocator.set(index);
+            allocator.free_frames -= 1;
+        }
+
+        allocator
+    }
+
+    /// Returns the number of usable frames managed by this allocator.
+    pub fn total_frames(&self) -> usize {
+        self.usable_frames
+    }
+
+    /// Returns the number of frames the bitmap covers, usable or not; frames
+    /// at or above this index are never allocated.
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Returns the number of usable frames that are currently allocated.
+    pub fn used_frames(&self) -> usize {
+        self.usable_frames - self.free_frames
+    }
+
+    /// Returns whether the given frame is currently allocated or not usable.
+    pub fn is_used(&self, frame: PhysFrame) -> bool {
+        let index = Self::frame_index(frame);
+        index >= self.frame_count || self.test(index)
+    }
+
+    /// Allocates a frame that ends below `limit`, e.g. for code run in real mode.
+    pub fn allocate_frame_below(&mut self, limit: PhysAddr) -> Option<PhysFrame> {
+        let end = ((limit.as_u64() / FRAME_SIZE) as usize).min(self.frame_count);
+        let index = (0..end).find(|&index| !self.test(index))?;
+
+        self.set(index);
+        self.free_frames -= 1;
+        let addr = PhysAddr::new(index as u64 * FRAME_SIZE);
+        Some(PhysFrame::containing_address(addr))
+    }
+
+    /// Allocates `count` contiguous frames whose first frame number is a
+    /// multiple of `align`, and returns the first frame.
+    ///
+    /// Used to hand whole blocks to other allocators, e.g. a
+    /// `BuddyFrameAllocator`, which then own them until they are freed here.
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrame> {
+        let align = align.max(1);
+        let mut start = 0;
+        while start + count <= self.frame_count {
+            match (start..start + count).rev().find(|&index| self.test(index)) {
+                // skip past the frame in use
+                Some(used) => start = (used / align + 1) * align,
+                None => {
+                    for index in start..start + count {
+                        self.set(index);
+                    }
+                    self.free_frames -= count;
+                    let addr = PhysAddr::new(start as u64 * FRAME_SIZE);
+                    return Some(PhysFrame::containing_address(addr));
+                }
+            }
+        }
+        None
+    }
+
+    fn frame_index(frame: PhysFrame) -> usize {
+        (frame.start_address().as_u64() / FRAME_SIZE) as usize
+    }
+
+    fn test(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+
+    fn clear(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let words = self.bitmap.len();
+        // start at the hint and wrap around once
+        for offset in 0..words {
+            let word_index = (self.next + offset) % words;
+            let word = self.bitmap[word_index];
+            if word == !0 {
+                continue; // every frame in this word is in use
+            }
+
+            let index = word_index * BITS_PER_WORD + (!word).trailing_zeros() as usize;
+            if index >= self.frame_count {
+                continue;
+            }
+
+            self.set(index);
+            self.free_frames -= 1;
+            self.next = word_index;
+            let addr = PhysAddr::new(index as u64 * FRAME_SIZE);
+            return Some(PhysFrame::containing_address(addr));
+        }
+
+        // out of physical memory
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let index = Self::frame_index(frame);
+        assert!(index < self.frame_count, "frame {:?} is not managed", frame);
+        assert!(self.test(index), "frame {:?} freed twice", frame);
+
+        self.clear(index);
+        self.free_frames += 1;
+        self.next = self.next.min(index / BITS_PER_WORD);
+    }
+}
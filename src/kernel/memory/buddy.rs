@@ -0,0 +1,225 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+const FRAME_SIZE: u64 = 4096;
+
+/// The largest supported order; a block of order `n` spans `2^n` frames,
+/// so `MAX_ORDER` blocks are 4 MiB.
+pub const MAX_ORDER: usize = 10;
+
+/// Header written into the first frame of every free block.
+struct FreeBlock {
+    next: Option<PhysFrame>,
+}
+
+/// A buddy-system physical frame allocator.
+///
+/// Serves physically contiguous runs of `2^order` frames that are naturally
+/// aligned to their own size. Free blocks are kept in one intrusive list per
+/// order, stored in the free frames themselves through the bootloader's
+/// physical memory mapping. Freed blocks are merged with their buddy whenever
+/// the buddy is free as well.
+pub struct BuddyFrameAllocator {
+    free_lists: [Option<PhysFrame>; MAX_ORDER + 1],
+    physical_memory_offset: VirtAddr,
+    total_frames: usize,
+    free_frames: usize,
+}
+
+impl BuddyFrameAllocator {
+    /// Creates an empty BuddyFrameAllocator.
+    pub const fn new(physical_memory_offset: VirtAddr) -> Self {
+        BuddyFrameAllocator {
+            free_lists: [None; MAX_ORDER + 1],
+            physical_memory_offset,
+            total_frames: 0,
+            free_frames: 0,
+        }
+    }
+
+    /// Create a BuddyFrameAllocator owning every usable region of the passed
+    /// memory map.
+    ///
+    /// This function is unsafe because the caller must guarantee that the passed
+    /// memory map is valid and that all frames marked as `USABLE` in it are really
+    /// unused. The complete physical memory must also be mapped at the passed
+    /// `physical_memory_offset`. The usable regions must not be handed to any
+    /// other frame allocator, so this can not be used next to the kernel's
+    /// `BitmapFrameAllocator`; the kernel seeds its pool with `add_region`
+    /// instead, see `memory::allocate_contiguous`.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let mut allocator = Self::new(physical_memory_offset);
+        let usable_regions = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable);
+        for region in usable_regions {
+            allocator.add_region(
+                PhysAddr::new(region.range.start_addr()),
+                PhysAddr::new(region.range.end_addr()),
+            );
+        }
+        allocator
+    }
+
+    /// Adds the frames in `start..end` to the allocator.
+    ///
+    /// This method is unsafe because the caller must guarantee that the given
+    /// range is unused and mapped at the physical memory offset.
+    pub unsafe fn add_region(&mut self, start: PhysAddr, end: PhysAddr) {
+        let mut frame = start.align_up(FRAME_SIZE).as_u64() / FRAME_SIZE;
+        let end = end.align_down(FRAME_SIZE).as_u64() / FRAME_SIZE;
+
+        while frame < end {
+            // the largest naturally aligned block that still fits the region
+            let mut order = (frame.trailing_zeros() as usize).min(MAX_ORDER);
+            while frame + (1 << order) > end {
+                order -= 1;
+            }
+
+            let block = PhysFrame::containing_address(PhysAddr::new(frame * FRAME_SIZE));
+            self.total_frames += 1 << order;
+            self.deallocate(block, order);
+            frame += 1 << order;
+        }
+    }
+
+    /// Returns the smallest order whose blocks hold at least `frames` frames.
+    pub fn order_for(frames: usize) -> Option<usize> {
+        let order = frames.max(1).next_power_of_two().trailing_zeros() as usize;
+        if order <= MAX_ORDER {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    /// Allocates `2^order` contiguous frames aligned to their combined size.
+    ///
+    /// Returns the first frame of the block.
+    pub fn allocate(&mut self, order: usize) -> Option<PhysFrame> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        // find the smallest free block that is large enough
+        let mut current = (order..=MAX_ORDER).find(|&o| self.free_lists[o].is_some())?;
+        let block = self.pop(current).unwrap();
+
+        // split it, returning the upper halves to the free lists
+        while current > order {
+            current -= 1;
+            unsafe { self.push(block + (1u64 << current), current) };
+        }
+
+        self.free_frames -= 1 << order;
+        Some(block)
+    }
+
+    /// Frees a block previously returned by `allocate` with the same `order`.
+    ///
+    /// This method is unsafe because the caller must guarantee that the block
+    /// was allocated from this allocator with the given order and is no
+    /// longer in use.
+    pub unsafe fn deallocate(&mut self, frame: PhysFrame, order: usize) {
+        assert!(order <= MAX_ORDER, "invalid block order {}", order);
+        let block_frames = 1u64 << order;
+        let frame_number = frame.start_address().as_u64() / FRAME_SIZE;
+        assert_eq!(frame_number % block_frames, 0, "misaligned block");
+
+        self.free_frames += 1 << order;
+
+        // merge with the buddy for as long as it is free
+        let mut frame_number = frame_number;
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy_number = frame_number ^ (1 << order);
+            let buddy = PhysFrame::containing_address(PhysAddr::new(buddy_number * FRAME_SIZE));
+            if !self.remove(buddy, order) {
+                break;
+            }
+            frame_number = frame_number.min(buddy_number);
+            order += 1;
+        }
+
+        let block = PhysFrame::containing_address(PhysAddr::new(frame_number * FRAME_SIZE));
+        self.push(block, order);
+    }
+
+    /// Returns the number of frames owned by this allocator.
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Returns the number of frames that are currently allocated.
+    pub fn used_frames(&self) -> usize {
+        self.total_frames - self.free_frames
+    }
+
+    /// Returns a pointer to the header stored in the given free frame.
+    fn block_header(&self, frame: PhysFrame) -> *mut FreeBlock {
+        let virt = self.physical_memory_offset + frame.start_address().as_u64();
+        virt.as_mut_ptr()
+    }
+
+    /// Pushes a free block to the front of the list for `order`.
+    unsafe fn push(&mut self, frame: PhysFrame, order: usize) {
+        let header = FreeBlock {
+            next: self.free_lists[order].take(),
+        };
+        self.block_header(frame).write(header);
+        self.free_lists[order] = Some(frame);
+    }
+
+    /// Pops the first free block of the list for `order`.
+    fn pop(&mut self, order: usize) -> Option<PhysFrame> {
+        let frame = self.free_lists[order]?;
+        self.free_lists[order] = unsafe { (*self.block_header(frame)).next };
+        Some(frame)
+    }
+
+    /// Unlinks `frame` from the list for `order`.
+    ///
+    /// Returns `false` if the block is not in that list.
+    fn remove(&mut self, frame: PhysFrame, order: usize) -> bool {
+        if self.free_lists[order] == Some(frame) {
+            self.pop(order);
+            return true;
+        }
+
+        let mut current = self.free_lists[order];
+        while let Some(block) = current {
+            let header = self.block_header(block);
+            let next = unsafe { (*header).next };
+            if next == Some(frame) {
+                unsafe { (*header).next = (*self.block_header(frame)).next };
+                return true;
+            }
+            current = next;
+        }
+
+        false
+    }
+}
+
+// The free lists are only reachable through this allocator.
+unsafe impl Send for BuddyFrameAllocator {}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate(0)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.deallocate(frame, 0)
+    }
+}
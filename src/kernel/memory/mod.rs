@@ -0,0 +1,289 @@
+use bitmap::BitmapFrameAllocator;
+use buddy::{BuddyFrameAllocator, MAX_ORDER};
+use core::mem::size_of;
+use core::slice;
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+        PhysFrame, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+pub mod address_space;
+pub mod bitmap;
+pub mod buddy;
+pub mod vma;
+
+/// The kernel page table, once handed over by `install`.
+static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+/// The physical frame allocator, once handed over by `install`.
+static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+/// The pool `allocate_contiguous` serves blocks from, created on first use.
+static CONTIGUOUS_FRAMES: Mutex<Option<BuddyFrameAllocator>> = Mutex::new(None);
+/// The virtual address at which the complete physical memory is mapped.
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+/// The physical address of the kernel's level 4 table.
+static KERNEL_PAGE_TABLE: AtomicU64 = AtomicU64::new(0);
+
+/// The number of additional references to every frame the frame allocator
+/// covers, e.g. by address spaces sharing it copy-on-write, by frame index.
+///
+/// The counters are atomic, so that page faults can share and release frames
+/// without taking a lock. Set up by `install`.
+static FRAME_REFS: AtomicPtr<AtomicU32> = AtomicPtr::new(core::ptr::null_mut());
+/// The number of counters in `FRAME_REFS`.
+static FRAME_REFS_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// The virtual region in which device memory is mapped by `map_mmio`.
+pub const MMIO_START: u64 = 0x_5555_5555_0000;
+pub const MMIO_SIZE: u64 = 1024 * 1024 * 1024; // 1 GiB
+/// The next unused address of the MMIO region.
+static MMIO_NEXT: AtomicU64 = AtomicU64::new(MMIO_START);
+
+/// Initialize a new OffsetPageTable.
+///
+/// This function is unsafe because the caller must guarantee that the
+/// complete physical memory is mapped to virtual memory at the passed
+/// `physical_memory_offset`. Also, this function must be only called once
+/// to avoid aliasing `&mut` references (which is undefined behavior).
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
+    let (level_4_table_frame, _) = x86_64::registers::control::Cr3::read();
+    KERNEL_PAGE_TABLE.store(
+        level_4_table_frame.start_address().as_u64(),
+        Ordering::Relaxed,
+    );
+    let level_4_table = active_level_4_table(physical_memory_offset);
+    OffsetPageTable::new(level_4_table, physical_memory_offset)
+}
+
+/// Returns a mutable reference to the active level 4 table.
+///
+/// This function is unsafe because the caller must guarantee that the
+/// complete physical memory is mapped to virtual memory at the passed
+/// `physical_memory_offset`. Also, this function must be only called once
+/// to avoid aliasing `&mut` references (which is undefined behavior).
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    use x86_64::registers::control::Cr3;
+
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let phys = level_4_table_frame.start_address();
+    let virt = physical_memory_offset + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    &mut *page_table_ptr // unsafe
+}
+
+/// Hands the kernel page table and the frame allocator over to the kernel, so
+/// that they can be used after boot (e.g. to grow the heap).
+pub fn install(mapper: OffsetPageTable<'static>, mut frame_allocator: BitmapFrameAllocator) {
+    // the counters take frames of their own, the heap may be too small
+    let len = frame_allocator.frame_count();
+    let frames = (len * size_of::<AtomicU32>() + Page::<Size4KiB>::SIZE as usize - 1)
+        / Page::<Size4KiB>::SIZE as usize;
+    let refs = frame_allocator
+        .allocate_contiguous(frames, 1)
+        .expect("no memory for the frame reference counters");
+    let refs = phys_to_virt(refs.start_address()).as_mut_ptr::<AtomicU32>();
+    unsafe { core::ptr::write_bytes(refs, 0, len) };
+    FRAME_REFS.store(refs, Ordering::SeqCst);
+    FRAME_REFS_LEN.store(len, Ordering::SeqCst);
+
+    *MAPPER.lock() = Some(mapper);
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+}
+
+/// Runs `f` with exclusive access to the kernel page table and the frame
+/// allocator.
+///
+/// Returns `None` if `install` was not called yet. The locks are always taken
+/// in the same order; `f` must not allocate from the heap because heap growth
+/// takes them as well.
+pub fn with_kernel_memory<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut OffsetPageTable<'static>, &mut BitmapFrameAllocator) -> R,
+{
+    interrupts::without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => Some(f(mapper, frame_allocator)),
+            _ => None,
+        }
+    })
+}
+
+/// Returns the frame of the kernel's level 4 table, which kernel threads use.
+pub fn kernel_page_table() -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(KERNEL_PAGE_TABLE.load(Ordering::Relaxed)))
+}
+
+/// Returns the virtual address through which the given physical address can
+/// be accessed.
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
+/// Returns the physical address of a virtual address obtained by `phys_to_virt`.
+pub fn virt_to_phys(addr: VirtAddr) -> PhysAddr {
+    PhysAddr::new(addr.as_u64() - PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
+}
+
+/// Allocates a physical frame from the kernel's frame allocator.
+///
+/// Returns `None` if physical memory ran out or `install` was not called yet.
+pub fn allocate_frame() -> Option<PhysFrame> {
+    with_frame_allocator(|frame_allocator| frame_allocator.allocate_frame())
+}
+
+/// Allocates a physical frame that ends below `limit`.
+///
+/// Returns `None` if there is no such frame or `install` was not called yet.
+pub fn allocate_frame_below(limit: PhysAddr) -> Option<PhysFrame> {
+    with_frame_allocator(|frame_allocator| frame_allocator.allocate_frame_below(limit))
+}
+
+/// Returns a frame to the kernel's frame allocator.
+///
+/// This function is unsafe because the caller must guarantee that the frame
+/// was allocated through `allocate_frame` and is no longer in use.
+pub unsafe fn deallocate_frame(frame: PhysFrame<Size4KiB>) {
+    with_frame_allocator(|frame_allocator| {
+        frame_allocator.deallocate_frame(frame);
+        Some(())
+    });
+}
+
+/// Runs `f` with the kernel's frame allocator, returning `None` if `install`
+/// was not called yet.
+///
+/// The lock is only held with interrupts disabled, so that code running with
+/// them disabled, e.g. a page fault handler, never spins on a holder that was
+/// preempted on the same CPU.
+fn with_frame_allocator<R>(f: impl FnOnce(&mut BitmapFrameAllocator) -> Option<R>) -> Option<R> {
+    interrupts::without_interrupts(|| f(FRAME_ALLOCATOR.lock().as_mut()?))
+}
+
+/// Allocates `2^order` physically contiguous frames, aligned to their combined
+/// size, e.g. for DMA buffers, and returns the first frame.
+///
+/// The blocks come from a buddy allocator, which takes blocks of `MAX_ORDER`
+/// out of the kernel's frame allocator when it runs out; they are never
+/// given back. Returns `None` if there is no such block or `install` was not
+/// called yet.
+pub fn allocate_contiguous(order: usize) -> Option<PhysFrame> {
+    if order > MAX_ORDER {
+        return None;
+    }
+    let mut pool = CONTIGUOUS_FRAMES.lock();
+    let pool = pool.get_or_insert_with(|| BuddyFrameAllocator::new(phys_to_virt(PhysAddr::new(0))));
+    if let Some(block) = pool.allocate(order) {
+        return Some(block);
+    }
+
+    let frames = 1 << MAX_ORDER;
+    let block = with_frame_allocator(|frame_allocator| {
+        frame_allocator.allocate_contiguous(frames, frames)
+    })?;
+    let start = block.start_address();
+    unsafe { pool.add_region(start, start + frames as u64 * Page::<Size4KiB>::SIZE) };
+    pool.allocate(order)
+}
+
+/// Frees a block returned by `allocate_contiguous`.
+///
+/// This function is unsafe because the caller must guarantee that the block
+/// was allocated with the same `order` and is no longer in use.
+pub unsafe fn deallocate_contiguous(frame: PhysFrame, order: usize) {
+    if let Some(pool) = CONTIGUOUS_FRAMES.lock().as_mut() {
+        pool.deallocate(frame, order);
+    }
+}
+
+/// Returns the reference counter of `frame`, or `None` if `install` was not
+/// called yet or the frame allocator does not cover the frame.
+fn frame_refs(frame: PhysFrame) -> Option<&'static AtomicU32> {
+    let refs = FRAME_REFS.load(Ordering::SeqCst);
+    if refs.is_null() {
+        return None;
+    }
+    let refs = unsafe { slice::from_raw_parts(refs, FRAME_REFS_LEN.load(Ordering::SeqCst)) };
+    refs.get((frame.start_address().as_u64() / Page::<Size4KiB>::SIZE) as usize)
+}
+
+/// Adds a reference to a frame allocated through `allocate_frame`, which has
+/// one reference to begin with.
+pub fn share_frame(frame: PhysFrame) {
+    let refs = frame_refs(frame).expect("shared frame not from the frame allocator");
+    refs.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Returns whether there is more than one reference to `frame`.
+pub fn frame_is_shared(frame: PhysFrame) -> bool {
+    frame_refs(frame).map_or(false, |refs| refs.load(Ordering::SeqCst) > 0)
+}
+
+/// Drops a reference to `frame` and returns it to the frame allocator if it
+/// was the last one.
+///
+/// This function is unsafe because the caller must guarantee that the frame
+/// was allocated through `allocate_frame` and that it no longer uses it.
+pub unsafe fn release_frame(frame: PhysFrame) {
+    if let Some(refs) = frame_refs(frame) {
+        let mut count = refs.load(Ordering::SeqCst);
+        while count > 0 {
+            match refs.compare_exchange_weak(count, count - 1, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return,
+                Err(current) => count = current,
+            }
+        }
+    }
+    deallocate_frame(frame);
+}
+
+/// Fills a frame with zeros.
+///
+/// This function is unsafe because the caller must guarantee that the frame
+/// is not in use by anything else.
+pub unsafe fn zero_frame(frame: PhysFrame) {
+    let page = phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+    core::ptr::write_bytes(page, 0, Page::<Size4KiB>::SIZE as usize);
+}
+
+/// Maps `size` bytes of device memory starting at `addr` as uncached memory.
+///
+/// Returns the virtual address of `addr`, or `None` if the MMIO region or
+/// physical memory for page tables ran out. Mappings are never removed.
+pub fn map_mmio(addr: PhysAddr, size: u64) -> Option<VirtAddr> {
+    let first = PhysFrame::<Size4KiB>::containing_address(addr);
+    let last = PhysFrame::<Size4KiB>::containing_address(addr + size.max(1) - 1u64);
+    let frames = PhysFrame::range_inclusive(first, last);
+    let len = (last.start_address() - first.start_address()) + Page::<Size4KiB>::SIZE;
+
+    let start = MMIO_NEXT.fetch_add(len, Ordering::Relaxed);
+    if start + len > MMIO_START + MMIO_SIZE {
+        return None;
+    }
+
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_CACHE;
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+    with_kernel_memory(|mapper, frame_allocator| {
+        for (index, frame) in frames.enumerate() {
+            let page = page + index as u64;
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator) }
+                .ok()?
+                .flush();
+        }
+        Some(())
+    })??;
+
+    Some(VirtAddr::new(start) + (addr - first.start_address()))
+}
@@ -0,0 +1,217 @@
+//! # Virtual memory areas
+//!
+//! Every address space describes the user memory it may use with a list of
+//! areas. Their pages are only mapped when first touched, see
+//! `AddressSpace::resolve_fault`: anonymous memory and stacks start zeroed,
+//! file-backed memory with the contents of the file.
+
+use super::address_space::MapError;
+use crate::kernel::process::File;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+/// What the memory of an area is initialized with.
+#[derive(Debug, Clone)]
+pub enum VmaKind {
+    /// Zeroed memory.
+    Anonymous,
+    /// The contents of `file` from `offset` on, zeroed past its end.
+    File { file: Arc<dyn File>, offset: u64 },
+    /// Zeroed memory growing downward on faults below it, as far as `limit`.
+    Stack { limit: VirtAddr },
+}
+
+/// A page-aligned range of user memory.
+#[derive(Debug, Clone)]
+pub struct Vma {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    /// The flags the pages are mapped with; `PRESENT` and `USER_ACCESSIBLE`
+    /// are added.
+    pub flags: PageTableFlags,
+    pub kind: VmaKind,
+}
+
+impl Vma {
+    pub fn new(start: VirtAddr, end: VirtAddr, flags: PageTableFlags, kind: VmaKind) -> Self {
+        Vma {
+            start,
+            end,
+            flags,
+            kind,
+        }
+    }
+
+    /// Returns whether `addr` is in the area.
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// Splits the area at `addr`, keeping the part below it and returning the
+    /// rest. A stack keeps the room to grow into.
+    fn split_off(&mut self, addr: VirtAddr) -> Vma {
+        let kind = match &self.kind {
+            VmaKind::File { file, offset } => VmaKind::File {
+                file: file.clone(),
+                offset: offset + (addr - self.start),
+            },
+            _ => VmaKind::Anonymous,
+        };
+        let rest = Vma::new(addr, self.end, self.flags, kind);
+        self.end = addr;
+        rest
+    }
+
+    /// Returns the start of the addresses reserved for the area, which
+    /// includes the room a stack may grow into.
+    pub fn reserved_start(&self) -> VirtAddr {
+        match self.kind {
+            VmaKind::Stack { limit } => limit,
+            _ => self.start,
+        }
+    }
+}
+
+/// The areas of an address space, which do not overlap.
+#[derive(Debug, Clone, Default)]
+pub struct VmaList {
+    /// The areas by end address, which does not change when stacks grow.
+    map: BTreeMap<VirtAddr, Vma>,
+}
+
+impl VmaList {
+    pub fn new() -> Self {
+        VmaList {
+            map: BTreeMap::new(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.map.values()
+    }
+
+    /// Returns the area containing `addr`.
+    pub fn find(&self, addr: VirtAddr) -> Option<&Vma> {
+        self.reserving(addr).filter(|vma| vma.contains(addr))
+    }
+
+    /// Adds `vma`, which must be page-aligned and must not overlap the
+    /// reserved addresses of any other area.
+    pub fn insert(&mut self, vma: Vma) -> Result<(), MapError> {
+        debug_assert!(vma.reserved_start() <= vma.start && vma.start < vma.end);
+        debug_assert!(
+            vma.start.is_aligned(Page::<Size4KiB>::SIZE)
+                && vma.end.is_aligned(Page::<Size4KiB>::SIZE)
+        );
+        let overlaps = self
+            .map
+            .range(vma.reserved_start() + 1u64..)
+            .next()
+            .map_or(false, |next| next.reserved_start() < vma.end);
+        if overlaps {
+            return Err(MapError::AlreadyMapped);
+        }
+        self.map.insert(vma.end, vma);
+        Ok(())
+    }
+
+    /// Returns the area containing `page`, growing a stack down to it if
+    /// `page` is in the room reserved for the stack.
+    pub fn find_or_grow(&mut self, page: Page) -> Option<&Vma> {
+        let addr = page.start_address();
+        let end = self.reserving(addr)?.end;
+        let vma = self.map.get_mut(&end)?;
+        if addr < vma.start {
+            vma.start = addr;
+        }
+        Some(&*vma)
+    }
+
+    /// Returns the lowest address from `start` on where `len` bytes fit below
+    /// `end` without overlapping any area.
+    pub fn find_free(&self, start: VirtAddr, len: u64, end: VirtAddr) -> Option<VirtAddr> {
+        let mut candidate = start;
+        for vma in self.map.range(start + 1u64..).map(|(_, vma)| vma) {
+            if candidate.as_u64().saturating_add(len) <= vma.reserved_start().as_u64() {
+                break;
+            }
+            candidate = candidate.max(vma.end);
+        }
+        if candidate.as_u64().saturating_add(len) <= end.as_u64() {
+            Some(candidate)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether every address from `start` to `end` is in an area.
+    pub fn covers(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let mut covered = start;
+        for vma in self.map.range(start + 1u64..).map(|(_, vma)| vma) {
+            if covered >= end || vma.start > covered {
+                break;
+            }
+            covered = vma.end;
+        }
+        covered >= end
+    }
+
+    /// Removes the addresses from `start` to `end` from the areas and returns
+    /// the removed parts.
+    pub fn remove(&mut self, start: VirtAddr, end: VirtAddr) -> Vec<Vma> {
+        if start >= end {
+            return Vec::new();
+        }
+        self.split(start);
+        self.split(end);
+        let inside: Vec<VirtAddr> = self
+            .map
+            .range(start + 1u64..=end)
+            .filter(|(_, vma)| vma.start >= start)
+            .map(|(&key, _)| key)
+            .collect();
+        inside
+            .into_iter()
+            .filter_map(|key| self.map.remove(&key))
+            .collect()
+    }
+
+    /// Changes the flags of the areas from `start` to `end` to `flags`.
+    pub fn protect(&mut self, start: VirtAddr, end: VirtAddr, flags: PageTableFlags) {
+        if start >= end {
+            return;
+        }
+        self.split(start);
+        self.split(end);
+        for (_, vma) in self.map.range_mut(start + 1u64..=end) {
+            if vma.start >= start {
+                vma.flags = flags;
+            }
+        }
+    }
+
+    /// Splits the area containing `addr`, if any, so that an area starts at
+    /// `addr`.
+    fn split(&mut self, addr: VirtAddr) {
+        let end = match self.find(addr) {
+            Some(vma) if vma.start < addr => vma.end,
+            _ => return,
+        };
+        let mut vma = self.map.remove(&end).expect("area vanished");
+        let rest = vma.split_off(addr);
+        self.map.insert(vma.end, vma);
+        self.map.insert(rest.end, rest);
+    }
+
+    /// Returns the area whose reserved addresses contain `addr`.
+    fn reserving(&self, addr: VirtAddr) -> Option<&Vma> {
+        self.map
+            .range(addr + 1u64..)
+            .next()
+            .map(|(_, vma)| vma)
+            .filter(|vma| vma.reserved_start() <= addr)
+    }
+}
@@ -1,7 +1,17 @@
 /// TOY OS KERNEL
+pub mod acpi;
 pub mod allocator;
+pub mod backtrace;
 pub mod context;
 pub mod devices;
+pub mod exec;
 pub mod interrupts;
 pub mod memory;
 pub mod panic;
+pub mod percpu;
+pub mod power;
+pub mod process;
+pub mod smp;
+pub mod sync;
+pub mod syscall;
+pub mod usermode;
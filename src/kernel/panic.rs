@@ -1,21 +1,79 @@
+use crate::kernel::devices::{serial::SERIAL1, vga::WRITER};
+use crate::kernel::{backtrace, percpu};
 use crate::qemu::{exit_qemu, QemuExitCode};
+use crate::serial_println;
+use crate::task::TaskId;
+use core::fmt::{self, Write};
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-pub fn test_panic_handler(_info: &PanicInfo) -> ! {
-    // serial_println!("[failed]\n");
-    // serial_println!("Error: {}\n", info);
+/// Set by the first panic.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    if crate::testing::panic_expected() {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }
+
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
     exit_qemu(QemuExitCode::Failed);
 }
 
-#[cfg(not(test))]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    // println!("{}", info);
+fn panic(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    if crate::testing::is_running() {
+        test_panic_handler(info);
+    }
+
+    // A panic while reporting a panic (e.g. on a corrupted stack) only gets
+    // the bare minimum of output.
+    let nested = PANICKING.swap(true, Ordering::SeqCst);
+
+    // The panic may have happened while the output devices were locked, by
+    // this CPU (which will never unlock them) or by another one.
+    unsafe {
+        SERIAL1.force_unlock();
+        WRITER.force_unlock();
+    }
+    // Output is best effort from here on; a failing write must not panic again.
+    let rbp = backtrace::current_frame();
+    let _ = write_report(&mut *SERIAL1.lock(), info, rbp, nested);
+    let _ = write_report(&mut *WRITER.lock(), info, rbp, nested);
+
     crate::hlt_loop();
 }
 
-#[cfg(test)]
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    test_panic_handler(info)
+/// Writes a description of the panic to `out`.
+fn write_report(out: &mut impl Write, info: &PanicInfo, rbp: usize, nested: bool) -> fmt::Result {
+    match percpu::try_current() {
+        Some(cpu) => write!(out, "\nKERNEL PANIC on CPU {}", cpu.cpu_id())?,
+        None => write!(out, "\nKERNEL PANIC during early boot")?,
+    }
+    match TaskId::current() {
+        Some(task) => writeln!(out, " in task {}", task.as_u64())?,
+        None => writeln!(out, " outside of any task")?,
+    }
+
+    match info.message() {
+        Some(message) => writeln!(out, "{}", message)?,
+        None => writeln!(out, "(no message)")?,
+    }
+    if let Some(location) = info.location() {
+        writeln!(
+            out,
+            "at {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        )?;
+    }
+
+    if !nested {
+        backtrace::write_backtrace(out, rbp)?;
+    }
+    Ok(())
 }
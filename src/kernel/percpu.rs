@@ -0,0 +1,211 @@
+//! # Per-CPU data
+//!
+//! Every CPU has a `PerCpu` block, which its GS base points to while it runs
+//! kernel code. User code gets its own GS base; interrupt and system call
+//! entries switch between the two with `swapgs`, see `KernelGsGuard`.
+//!
+//! Statics with one instance per CPU are declared with `per_cpu!`.
+
+use crate::kernel::context::ContextId;
+use crate::kernel::smp::MAX_CPUS;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::cell::Cell;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use spin::Mutex;
+use x86_64::registers::model_specific::Msr;
+
+/// The `IA32_GS_BASE` MSR, the GS base in use.
+const GS_BASE: u32 = 0xc000_0101;
+/// The `IA32_KERNEL_GS_BASE` MSR, the GS base `swapgs` switches to.
+const KERNEL_GS_BASE: u32 = 0xc000_0102;
+
+const NO_CPU: AtomicPtr<PerCpu> = AtomicPtr::new(ptr::null_mut());
+
+/// The per-CPU blocks of all CPUs, by CPU id.
+static CPUS: [AtomicPtr<PerCpu>; MAX_CPUS] = [NO_CPU; MAX_CPUS];
+
+/// The data private to a CPU.
+#[repr(C)]
+pub struct PerCpu {
+    /// The address of this block, so that it can be found through `gs:[0]`.
+    this: *const PerCpu,
+    /// The stack the system call entry switches to, at `gs:[8]`.
+    pub kernel_rsp: Cell<usize>,
+    /// The user stack pointer during a system call, at `gs:[16]`.
+    user_rsp: Cell<usize>,
+    cpu_id: usize,
+    /// The context running on this CPU.
+    pub current_context: Cell<Option<ContextId>>,
+    /// The context this CPU is switching away from, until the switch finished.
+    pub switched_from: Cell<Option<ContextId>>,
+    /// The contexts waiting to run on this CPU.
+    pub run_queue: Mutex<VecDeque<ContextId>>,
+    /// The number of interrupt handlers running on this CPU.
+    pub irq_depth: Cell<usize>,
+}
+
+// The cells are only used by the CPU owning the block, the rest is locked.
+unsafe impl Sync for PerCpu {}
+
+impl PerCpu {
+    /// Returns the id of the CPU owning this block.
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id
+    }
+
+    /// Returns whether the CPU is running an interrupt handler.
+    pub fn in_interrupt(&self) -> bool {
+        self.irq_depth.get() > 0
+    }
+}
+
+/// Creates the per-CPU block of the executing CPU and points its GS base to it.
+///
+/// Must be called once per CPU, after the heap was initialized.
+pub fn init(cpu_id: usize) {
+    let block = Box::leak(Box::new(PerCpu {
+        this: ptr::null(),
+        kernel_rsp: Cell::new(0),
+        user_rsp: Cell::new(0),
+        cpu_id,
+        current_context: Cell::new(None),
+        switched_from: Cell::new(None),
+        run_queue: Mutex::new(VecDeque::new()),
+        irq_depth: Cell::new(0),
+    }));
+    let block: *mut PerCpu = block;
+    unsafe { (*block).this = block };
+
+    CPUS[cpu_id].store(block, Ordering::SeqCst);
+    unsafe {
+        Msr::new(GS_BASE).write(block as u64);
+        // there is no user GS base yet
+        Msr::new(KERNEL_GS_BASE).write(0);
+    }
+}
+
+/// Returns the per-CPU block of the executing CPU.
+///
+/// Must only be called after `init` ran on this CPU, with the kernel's GS base.
+pub fn current() -> &'static PerCpu {
+    let block: *const PerCpu;
+    unsafe {
+        asm!(
+            "mov {}, qword ptr gs:[0]",
+            out(reg) block,
+            options(nostack, readonly, preserves_flags)
+        );
+        &*block
+    }
+}
+
+/// Returns the per-CPU block of the executing CPU, or `None` if `init` did not
+/// run yet.
+///
+/// Slower than `current`, but safe to call at any time, e.g. while panicking.
+pub fn try_current() -> Option<&'static PerCpu> {
+    let block = unsafe { Msr::new(GS_BASE).read() } as *const PerCpu;
+    if block.is_null() {
+        return None;
+    }
+    // the GS base may belong to user code
+    CPUS.iter()
+        .map(|cpu| cpu.load(Ordering::SeqCst) as *const PerCpu)
+        .find(|&cpu| cpu == block)
+        .map(|cpu| unsafe { &*cpu })
+}
+
+/// Returns the per-CPU block of the CPU with the given id, if it was started.
+pub fn get(cpu_id: usize) -> Option<&'static PerCpu> {
+    let block = CPUS.get(cpu_id)?.load(Ordering::SeqCst);
+    unsafe { block.as_ref() }
+}
+
+/// Returns the id of the executing CPU.
+pub fn cpu_id() -> usize {
+    current().cpu_id
+}
+
+/// Switches to the kernel's GS base in an interrupt handler if the user's is
+/// loaded, and back to the user's when dropped.
+///
+/// The interrupted privilege level does not tell which one is loaded: NMIs,
+/// machine checks and debug exceptions may arrive in the kernel right after
+/// `syscall`, or right before `sysretq`, while the user's GS base is loaded.
+///
+/// Must be created before anything in the handler uses per-CPU data.
+pub struct KernelGsGuard {
+    swapped: bool,
+}
+
+impl KernelGsGuard {
+    pub fn enter() -> Self {
+        // before `init` both GS bases are zero, swapping them does no harm
+        let swapped = try_current().is_none();
+        if swapped {
+            unsafe { asm!("swapgs", options(nostack, preserves_flags)) };
+        }
+        KernelGsGuard { swapped }
+    }
+}
+
+impl Drop for KernelGsGuard {
+    fn drop(&mut self) {
+        if self.swapped {
+            unsafe { asm!("swapgs", options(nostack, preserves_flags)) };
+        }
+    }
+}
+
+/// A static with one instance per CPU, declared with `per_cpu!`.
+///
+/// Like a `#[thread_local]` static, every CPU only sees its own instance.
+pub struct PerCpuStatic<T> {
+    values: [T; MAX_CPUS],
+}
+
+// Every CPU only accesses its own instance through `get`.
+unsafe impl<T: Send> Sync for PerCpuStatic<T> {}
+
+impl<T> PerCpuStatic<T> {
+    #[doc(hidden)]
+    pub const fn new(values: [T; MAX_CPUS]) -> Self {
+        PerCpuStatic { values }
+    }
+
+    /// Returns the instance of the executing CPU.
+    pub fn get(&self) -> &T {
+        &self.values[cpu_id()]
+    }
+}
+
+impl<T: Sync> PerCpuStatic<T> {
+    /// Returns the instance of the CPU with the given id.
+    pub fn get_for(&self, cpu_id: usize) -> &T {
+        &self.values[cpu_id]
+    }
+}
+
+/// Declares a static with one instance per CPU.
+///
+/// ```ignore
+/// per_cpu! {
+///     static TICKS: AtomicU64 = AtomicU64::new(0);
+/// }
+///
+/// TICKS.get().fetch_add(1, Ordering::Relaxed);
+/// ```
+///
+/// The initializer must be a constant expression.
+#[macro_export]
+macro_rules! per_cpu {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;) => {
+        $(#[$attr])*
+        $vis static $name: $crate::kernel::percpu::PerCpuStatic<$ty> = {
+            const INIT: $ty = $init;
+            $crate::kernel::percpu::PerCpuStatic::new([INIT; $crate::kernel::smp::MAX_CPUS])
+        };
+    };
+}
@@ -0,0 +1,132 @@
+//! # Power off and reboot
+//!
+//! Both try the ACPI way first and fall back to methods that work on emulators
+//! and older hardware.
+
+use crate::kernel::acpi::{self, dsdt, AddressSpace};
+use crate::kernel::memory;
+use crate::println;
+use core::ptr;
+use x86_64::instructions::{interrupts, port::Port};
+use x86_64::PhysAddr;
+
+/// The sleep state that powers the machine off.
+const S5: u8 = 5;
+
+/// `SLP_EN` and the shift of `SLP_TYP` in the PM1 control registers.
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP_SHIFT: u16 = 10;
+/// Set in PM1a control while the system is in ACPI mode.
+const SCI_EN: u16 = 1 << 0;
+
+/// Shutdown ports of emulators: QEMU (with ACPI), Bochs and older QEMU, VirtualBox.
+const EMULATOR_SHUTDOWN: [(u16, u16); 3] = [(0x604, 0x2000), (0xb004, 0x2000), (0x4004, 0x3400)];
+
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 1 << 1;
+const KBC_PULSE_RESET: u8 = 0xfe;
+
+/// Powers the machine off.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+
+    if let Err(reason) = acpi_shutdown() {
+        println!("ACPI shutdown failed: {}", reason);
+    }
+
+    for &(port, value) in EMULATOR_SHUTDOWN.iter() {
+        unsafe { Port::<u16>::new(port).write(value) };
+    }
+
+    println!("It is now safe to turn off your computer.");
+    crate::hlt_loop();
+}
+
+/// Enters sleep state S5 through the PM1 control registers of the FADT.
+fn acpi_shutdown() -> Result<(), &'static str> {
+    let acpi = acpi::get().ok_or("no ACPI tables")?;
+    let fadt = acpi.fadt.ok_or("no FADT")?;
+    let dsdt = acpi.dsdt().ok_or("no valid DSDT")?;
+    let sleep_type = dsdt::sleep_type(&dsdt, S5).ok_or("no \\_S5 object")?;
+    if fadt.pm1a_control_block == 0 {
+        return Err("no PM1a control block");
+    }
+
+    let mut pm1a = Port::<u16>::new(fadt.pm1a_control_block as u16);
+    unsafe {
+        // the firmware may still own the power management hardware
+        if pm1a.read() & SCI_EN == 0 && fadt.smi_command_port != 0 && fadt.acpi_enable != 0 {
+            Port::<u8>::new(fadt.smi_command_port as u16).write(fadt.acpi_enable);
+            for _ in 0..1_000_000 {
+                if pm1a.read() & SCI_EN != 0 {
+                    break;
+                }
+            }
+        }
+
+        pm1a.write(u16::from(sleep_type.a) << SLP_TYP_SHIFT | SLP_EN);
+        if fadt.pm1b_control_block != 0 {
+            Port::<u16>::new(fadt.pm1b_control_block as u16)
+                .write(u16::from(sleep_type.b) << SLP_TYP_SHIFT | SLP_EN);
+        }
+    }
+    Err("machine still running")
+}
+
+/// Resets the machine.
+pub fn reboot() -> ! {
+    interrupts::disable();
+
+    let fadt = acpi::get().and_then(|acpi| acpi.fadt);
+    if let Some(fadt) = fadt {
+        if let Some(reset) = fadt.reset_register {
+            acpi_reset(reset, fadt.reset_value);
+        }
+    }
+
+    // without a FADT, assume the controller is there like on any PC
+    if fadt.map_or(true, |fadt| fadt.has_8042) {
+        pulse_8042_reset();
+    }
+
+    triple_fault();
+}
+
+/// Writes `value` to the ACPI reset register.
+fn acpi_reset(reset: acpi::GenericAddress, value: u8) {
+    match reset.address_space {
+        AddressSpace::SystemIo => unsafe { Port::<u8>::new(reset.address as u16).write(value) },
+        AddressSpace::SystemMemory => {
+            if let Some(register) = memory::map_mmio(PhysAddr::new(reset.address), 1) {
+                unsafe { ptr::write_volatile(register.as_mut_ptr::<u8>(), value) };
+            }
+        }
+        _ => println!("unsupported reset register {:?}", reset.address_space),
+    }
+}
+
+/// Pulses the reset line of the 8042 keyboard controller.
+fn pulse_8042_reset() {
+    unsafe {
+        let mut status = Port::<u8>::new(KBC_STATUS);
+        for _ in 0..1_000_000 {
+            if status.read() & KBC_INPUT_FULL == 0 {
+                break;
+            }
+        }
+        Port::<u8>::new(KBC_COMMAND).write(KBC_PULSE_RESET);
+    }
+}
+
+/// Resets the CPU by raising an exception without any IDT to handle it.
+fn triple_fault() -> ! {
+    use x86_64::instructions::tables::{lidt, DescriptorTablePointer};
+
+    let idt = DescriptorTablePointer { limit: 0, base: 0 };
+    unsafe {
+        lidt(&idt);
+        asm!("int3");
+    }
+    crate::hlt_loop();
+}
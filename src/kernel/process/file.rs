@@ -0,0 +1,113 @@
+use crate::kernel::syscall::{Error, Result, EBADF, ESPIPE};
+use crate::print;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The files every process starts with.
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+/// An open file, shared by all file descriptors referring to it.
+pub trait File: fmt::Debug + Send + Sync {
+    /// Reads into `buf` and returns the number of bytes read.
+    fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+        Err(Error::new(EBADF))
+    }
+
+    /// Writes `buf` and returns the number of bytes written.
+    fn write(&self, _buf: &[u8]) -> Result<usize> {
+        Err(Error::new(EBADF))
+    }
+
+    /// Reads into `buf` from `offset` on and returns the number of bytes
+    /// read, which is less than requested only at the end of the file.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> Result<usize> {
+        Err(Error::new(ESPIPE))
+    }
+}
+
+/// The screen and serial port.
+#[derive(Debug)]
+pub struct Console;
+
+impl File for Console {
+    /// There is no keyboard input yet, so reads see the end of the file.
+    fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        print!("{}", String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+}
+
+/// A read-only file held in memory, e.g. an executable image.
+#[derive(Debug)]
+pub struct MemoryFile {
+    data: Vec<u8>,
+}
+
+impl MemoryFile {
+    pub fn new(data: Vec<u8>) -> Self {
+        MemoryFile { data }
+    }
+}
+
+impl File for MemoryFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let start = (offset as usize).min(self.data.len());
+        let len = buf.len().min(self.data.len() - start);
+        buf[..len].copy_from_slice(&self.data[start..start + len]);
+        Ok(len)
+    }
+}
+
+/// The open files of a process, indexed by file descriptor.
+#[derive(Debug, Clone, Default)]
+pub struct FileTable {
+    files: Vec<Option<Arc<dyn File>>>,
+}
+
+impl FileTable {
+    pub fn new() -> Self {
+        FileTable { files: Vec::new() }
+    }
+
+    /// A table with `STDIN`, `STDOUT` and `STDERR` open on the console.
+    pub fn standard() -> Self {
+        let console: Arc<dyn File> = Arc::new(Console);
+        let mut files = FileTable::new();
+        for _ in STDIN..=STDERR {
+            files.open(console.clone());
+        }
+        files
+    }
+
+    pub fn get(&self, fd: usize) -> Option<&Arc<dyn File>> {
+        self.files.get(fd)?.as_ref()
+    }
+
+    /// Adds `file` at the lowest free descriptor and returns it.
+    pub fn open(&mut self, file: Arc<dyn File>) -> usize {
+        match self.files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.files[fd] = Some(file);
+                fd
+            }
+            None => {
+                self.files.push(Some(file));
+                self.files.len() - 1
+            }
+        }
+    }
+
+    /// Removes the file at `fd`, which is closed once the last descriptor
+    /// referring to it is gone.
+    pub fn close(&mut self, fd: usize) -> Option<Arc<dyn File>> {
+        self.files.get_mut(fd)?.take()
+    }
+}
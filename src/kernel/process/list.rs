@@ -0,0 +1,98 @@
+use super::process::{Process, ProcessId};
+use alloc::collections::BTreeMap;
+
+/// The highest process ID handed out.
+const MAX_PROCESSES: usize = (isize::max_value() as usize) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    /// All process IDs are in use.
+    TooManyProcesses,
+    /// All context IDs are in use.
+    TooManyContexts,
+}
+
+/// The table of all processes, by ID.
+#[derive(Debug)]
+pub struct ProcessList {
+    map: BTreeMap<ProcessId, Process>,
+    next_id: usize,
+}
+
+impl ProcessList {
+    pub fn new() -> Self {
+        ProcessList {
+            map: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn get(&self, id: ProcessId) -> Option<&Process> {
+        self.map.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: ProcessId) -> Option<&mut Process> {
+        self.map.get_mut(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Process> {
+        self.map.values()
+    }
+
+    /// Returns the processes started by `parent`, or by the kernel if `None`.
+    pub fn children(&self, parent: Option<ProcessId>) -> impl Iterator<Item = &Process> {
+        self.map
+            .values()
+            .filter(move |process| process.parent == parent)
+    }
+
+    /// Adds a process with a fresh ID and without threads to the table.
+    pub fn new_process(
+        &mut self,
+        name: &'static str,
+        parent: Option<ProcessId>,
+    ) -> Result<&mut Process, ProcessError> {
+        if self.next_id >= MAX_PROCESSES {
+            self.next_id = 1;
+        }
+        while self.map.contains_key(&ProcessId::new(self.next_id)) {
+            self.next_id += 1;
+            if self.next_id >= MAX_PROCESSES {
+                return Err(ProcessError::TooManyProcesses);
+            }
+        }
+
+        let id = ProcessId::new(self.next_id);
+        self.next_id += 1;
+
+        let process = Process::new(id, name, parent);
+        Ok(self.map.entry(id).or_insert(process))
+    }
+
+    /// Hands the children of `parent` to `new_parent`.
+    pub fn reparent(&mut self, parent: ProcessId, new_parent: Option<ProcessId>) {
+        for process in self.map.values_mut() {
+            if process.parent == Some(parent) {
+                process.parent = new_parent;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: ProcessId) -> Option<Process> {
+        self.map.remove(&id)
+    }
+}
+
+impl Default for ProcessList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
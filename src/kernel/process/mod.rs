@@ -0,0 +1,207 @@
+//! # Processes
+//!
+//! A process owns a user address space, an open-file table and the context
+//! running it as its only thread. Processes started by a process, e.g. with `fork`,
+//! are its children: when a process exits it becomes a zombie, keeping its
+//! exit status until the parent collects it with `waitpid`. The kernel is the
+//! parent of the processes it starts and adopts the children of exited
+//! processes.
+
+mod file;
+mod list;
+mod process;
+
+pub use file::{Console, File, FileTable, MemoryFile, STDERR, STDIN, STDOUT};
+pub use list::{ProcessError, ProcessList};
+pub use process::{Process, ProcessId, ProcessStatus};
+
+use crate::kernel::context::{self, ContextError, Status};
+use crate::kernel::memory::address_space::AddressSpace;
+use crate::kernel::sync::WaitQueue;
+use crate::kernel::syscall::{Error, Result, SyscallFrame, EAGAIN, ECHILD, ENOMEM, ESRCH};
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+
+lazy_static! {
+    /// Process list
+    static ref PROCESSES: RwLock<ProcessList> = RwLock::new(ProcessList::new());
+}
+
+/// The contexts waiting in `waitpid`, woken up whenever a process exits.
+static CHILD_EXITED: WaitQueue = WaitQueue::new();
+
+impl From<ContextError> for ProcessError {
+    fn from(err: ContextError) -> Self {
+        match err {
+            ContextError::TooManyContexts => ProcessError::TooManyContexts,
+        }
+    }
+}
+
+/// Get the global process list, const
+pub fn processes() -> RwLockReadGuard<'static, ProcessList> {
+    PROCESSES.read()
+}
+
+/// Get the global process list, mutable
+pub fn processes_mut() -> RwLockWriteGuard<'static, ProcessList> {
+    PROCESSES.write()
+}
+
+/// Returns the ID of the process the current context belongs to.
+pub fn process_id() -> Option<ProcessId> {
+    context::with_current(|context| context.process)?
+}
+
+/// Returns the file at `fd` of the current process.
+pub fn file(fd: usize) -> Option<Arc<dyn File>> {
+    let id = process_id()?;
+    processes().get(id)?.files.get(fd).cloned()
+}
+
+/// Starts a child of the current process with one thread running user code
+/// in `addr_space` at `ip`, with the stack pointer `sp`.
+///
+/// The process has the standard files open on the console.
+pub fn spawn_user(
+    name: &'static str,
+    addr_space: AddressSpace,
+    ip: VirtAddr,
+    sp: VirtAddr,
+) -> core::result::Result<ProcessId, ProcessError> {
+    let entry = SyscallFrame::new(ip, sp);
+    spawn(name, addr_space, FileTable::standard(), entry)
+}
+
+/// Starts a copy of the current process, with a copy-on-write copy of its
+/// address space and the same open files.
+///
+/// The only thread of the child continues from the system call `frame` of
+/// the calling thread, returning 0.
+pub fn fork(frame: &SyscallFrame) -> Result<ProcessId> {
+    let id = process_id().ok_or_else(|| Error::new(ESRCH))?;
+    let (name, addr_space, files) = {
+        let processes = processes();
+        let process = processes.get(id).ok_or_else(|| Error::new(ESRCH))?;
+        let addr_space = process.addr_space.clone();
+        (process.name, addr_space, process.files.clone())
+    };
+    let addr_space = addr_space.ok_or_else(|| Error::new(ESRCH))?;
+    let addr_space = addr_space.lock().fork().map_err(|_| Error::new(ENOMEM))?;
+
+    let mut entry = frame.clone();
+    entry.rax = 0;
+    spawn(name, addr_space, files, entry).map_err(|_| Error::new(EAGAIN))
+}
+
+/// Starts a child of the current process with one thread entering user code
+/// with the registers in `entry`.
+fn spawn(
+    name: &'static str,
+    addr_space: AddressSpace,
+    files: FileTable,
+    entry: SyscallFrame,
+) -> core::result::Result<ProcessId, ProcessError> {
+    let parent = process_id();
+    let addr_space = Arc::new(Mutex::new(addr_space));
+
+    // the thread must not exit before it is in the process
+    let mut processes = processes_mut();
+    let process = processes.new_process(name, parent)?;
+    let id = process.id;
+    match context::spawn_user(name, id, addr_space.clone(), entry) {
+        Ok(thread) => {
+            // the TLB handling of address spaces depends on it
+            debug_assert!(process.threads.is_empty(), "processes have one thread");
+            process.threads.push(thread);
+            process.addr_space = Some(addr_space);
+            process.files = files;
+            Ok(id)
+        }
+        Err(err) => {
+            processes.remove(id);
+            Err(err.into())
+        }
+    }
+}
+
+/// Exits the current process with `status`, terminating all its threads.
+///
+/// Kernel threads, which belong to no process, just exit themselves.
+pub fn exit(status: usize) -> ! {
+    if let Some(id) = process_id() {
+        let threads = {
+            let mut processes = processes_mut();
+            processes.reparent(id, None);
+
+            let process = processes.get_mut(id).expect("no current process");
+            process.status = ProcessStatus::Zombie(status);
+            // close the files and release the memory, once the threads stopped
+            process.files = FileTable::new();
+            process.addr_space = None;
+            process.threads.clone()
+        };
+        terminate_threads(&threads, status);
+        CHILD_EXITED.notify_all();
+    }
+    context::exit(status);
+}
+
+/// Terminates the threads other than the current one.
+///
+/// A thread running on another CPU stops at its next context switch.
+fn terminate_threads(threads: &[context::ContextId], status: usize) {
+    let current = context::context_id();
+    interrupts::without_interrupts(|| {
+        let contexts = context::contexts();
+        for &thread in threads.iter().filter(|&&thread| Some(thread) != current) {
+            if let Some(context_lock) = contexts.get(thread) {
+                context_lock.write().status = Status::Exited(status);
+            }
+        }
+    });
+}
+
+/// Waits for a child of the current process to exit and returns its ID and
+/// exit status.
+///
+/// With `pid` set, only that child is waited for. With `nohang` set, returns
+/// `None` instead of blocking if no child exited yet. Fails with `ECHILD` if
+/// there are no matching children.
+///
+/// The zombie is removed from the process list, along with its threads.
+pub fn waitpid(pid: Option<ProcessId>, nohang: bool) -> Result<Option<(ProcessId, usize)>> {
+    let parent = process_id();
+    let reaped = CHILD_EXITED.wait_until("waitpid", || {
+        let mut processes = processes_mut();
+        let mut children = processes
+            .children(parent)
+            .filter(|child| pid.map_or(true, |pid| child.id == pid))
+            .peekable();
+        if children.peek().is_none() {
+            return Some(Err(Error::new(ECHILD)));
+        }
+        let zombie = children.find_map(|child| match child.status {
+            ProcessStatus::Zombie(status) => Some((child.id, status)),
+            ProcessStatus::Alive => None,
+        });
+        match zombie {
+            Some((id, status)) => {
+                let process = processes.remove(id).expect("zombie vanished");
+                Some(Ok(Some((id, status, process.threads))))
+            }
+            None if nohang => Some(Ok(None)),
+            None => None,
+        }
+    })?;
+
+    Ok(reaped.map(|(id, status, threads)| {
+        for thread in threads {
+            context::reap(thread);
+        }
+        (id, status)
+    }))
+}
@@ -0,0 +1,63 @@
+use super::file::FileTable;
+use crate::kernel::context::ContextId;
+use crate::kernel::memory::address_space::AddressSpace;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// The status of a process.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProcessStatus {
+    Alive,
+    /// The process exited, but its parent did not collect the status with
+    /// `waitpid` yet.
+    Zombie(usize),
+}
+
+/// A program running in its own address space, with a single thread.
+///
+/// Address spaces rely on there being only one thread, see
+/// `memory::address_space`.
+#[derive(Debug)]
+pub struct Process {
+    /// The ID of this process
+    pub id: ProcessId,
+    /// A name for diagnostics
+    pub name: &'static str,
+    /// The process that started this one; `None` if it was started by the
+    /// kernel, which also adopts the children of exited processes
+    pub parent: Option<ProcessId>,
+    pub status: ProcessStatus,
+    /// The context running the process
+    pub threads: Vec<ContextId>,
+    /// The address space of the thread; `None` once exited
+    pub addr_space: Option<Arc<Mutex<AddressSpace>>>,
+    pub files: FileTable,
+}
+
+impl Process {
+    pub fn new(id: ProcessId, name: &'static str, parent: Option<ProcessId>) -> Self {
+        Process {
+            id,
+            name,
+            parent,
+            status: ProcessStatus::Alive,
+            threads: Vec::new(),
+            addr_space: None,
+            files: FileTable::new(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ProcessId(usize);
+
+impl ProcessId {
+    pub const fn new(id: usize) -> Self {
+        ProcessId(id)
+    }
+
+    pub fn as_usize(&self) -> usize {
+        self.0
+    }
+}
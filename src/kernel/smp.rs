@@ -0,0 +1,285 @@
+//! # Symmetric multiprocessing
+//!
+//! Starts the application processors (APs) listed in the ACPI MADT. Each AP
+//! is woken with the INIT-SIPI-SIPI sequence and starts in real mode at the
+//! trampoline below, which switches to long mode with the kernel page tables
+//! and calls `ap_entry` on the AP's own stack.
+//!
+//! APs are started one after another, so a single trampoline page is enough.
+
+use crate::kernel::devices::{apic, gdt};
+use crate::kernel::{acpi, context, interrupts, memory, percpu, syscall};
+use alloc::boxed::Box;
+use alloc::vec;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use x86_64::instructions::port::Port;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// The maximum number of CPUs the kernel supports.
+pub const MAX_CPUS: usize = 64;
+
+/// The size of the kernel stack of every AP.
+const AP_STACK_SIZE: usize = 64 * 1024;
+
+/// The trampoline runs in real mode, so it must be in the first MiB.
+const TRAMPOLINE_LIMIT: u64 = 0x10_0000;
+
+/// The interrupt command register values of the IPIs starting an AP.
+const ICR_INIT: u32 = 0x4500;
+const ICR_STARTUP: u32 = 0x4600;
+
+/// The selector of the 64-bit code segment of the trampoline's GDT.
+const TRAMPOLINE_CODE_SELECTOR: u16 = 8;
+
+/// The number of CPUs running the kernel.
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+/// Set by an AP once it no longer needs the trampoline.
+static AP_ARRIVED: AtomicBool = AtomicBool::new(false);
+
+global_asm!(
+    r#"
+.section .text.ap_trampoline, "ax"
+.intel_syntax noprefix
+
+.code16
+.global ap_trampoline_start
+ap_trampoline_start:
+    cli
+    cld
+    # the trampoline starts at offset zero of the code segment
+    mov ax, cs
+    mov ds, ax
+
+    # enable physical address extensions
+    mov eax, cr4
+    or eax, 1 << 5
+    mov cr4, eax
+
+    mov eax, dword ptr [ap_trampoline_page_table - ap_trampoline_start]
+    mov cr3, eax
+
+    # enable long mode and no-execute pages in EFER
+    mov ecx, 0xc0000080
+    rdmsr
+    or eax, (1 << 8) | (1 << 11)
+    wrmsr
+
+    # enable protection, write protection and paging
+    mov eax, cr0
+    or eax, (1 << 31) | (1 << 16) | 1
+    mov cr0, eax
+
+    lgdt [ap_trampoline_gdtr - ap_trampoline_start]
+    # jmp far dword [ap_trampoline_far_jump], loading the 64-bit code segment
+    .byte 0x66, 0xff, 0x2e
+    .word ap_trampoline_far_jump - ap_trampoline_start
+
+.code64
+.global ap_trampoline_long_mode
+ap_trampoline_long_mode:
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    mov fs, ax
+    mov gs, ax
+
+    mov rsp, qword ptr [rip + ap_trampoline_stack]
+    mov rdi, qword ptr [rip + ap_trampoline_cpu_id]
+    mov rax, qword ptr [rip + ap_trampoline_entry]
+    call rax
+    ud2
+
+# filled in as a `TrampolineData` before every AP is started
+.global ap_trampoline_data
+ap_trampoline_data:
+ap_trampoline_gdt: .skip 24
+ap_trampoline_gdtr: .skip 6
+ap_trampoline_far_jump: .skip 6
+ap_trampoline_page_table: .skip 8
+ap_trampoline_stack: .skip 8
+ap_trampoline_entry: .skip 8
+ap_trampoline_cpu_id: .skip 8
+.global ap_trampoline_end
+ap_trampoline_end:
+
+.att_syntax prefix
+.text
+"#
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_long_mode: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// The data at the end of the trampoline, see `ap_trampoline_data`.
+#[repr(C, packed)]
+struct TrampolineData {
+    gdt: [u64; 3],
+    gdtr_limit: u16,
+    gdtr_base: u32,
+    far_jump_offset: u32,
+    far_jump_selector: u16,
+    page_table: u64,
+    stack: u64,
+    entry: u64,
+    cpu_id: u64,
+}
+
+/// Starts all usable APs listed in the ACPI MADT.
+///
+/// Must be called once on the bootstrap processor, after the local APIC was
+/// enabled.
+pub fn init() {
+    let madt = match acpi::get().and_then(|acpi| acpi.madt.as_ref()) {
+        Some(madt) => madt,
+        None => return,
+    };
+    if !apic::is_enabled() {
+        return;
+    }
+
+    let frame = memory::allocate_frame_below(PhysAddr::new(TRAMPOLINE_LIMIT))
+        .expect("no memory below 1 MiB for the AP trampoline");
+    let mapped = identity_map(frame);
+    let trampoline = memory::phys_to_virt(frame.start_address());
+    unsafe {
+        let start = &ap_trampoline_start as *const u8;
+        let len = &ap_trampoline_end as *const u8 as usize - start as usize;
+        ptr::copy_nonoverlapping(start, trampoline.as_mut_ptr::<u8>(), len);
+    }
+
+    let bsp = apic::local::id();
+    for cpu in madt.processors.iter() {
+        if !cpu.usable || cpu.apic_id == bsp {
+            continue;
+        }
+        if CPU_COUNT.load(Ordering::SeqCst) == MAX_CPUS {
+            break;
+        }
+        if !start_ap(frame, trampoline, cpu.apic_id) {
+            crate::println!("WARNING: CPU with APIC ID {} did not start", cpu.apic_id);
+            // a CPU that was just slow may still run the trampoline, so it
+            // must stay mapped, and must not be given the next CPU's stack
+            // and id
+            crate::println!("WARNING: not starting the remaining CPUs");
+            return;
+        }
+    }
+
+    if mapped {
+        let page =
+            Page::<Size4KiB>::containing_address(VirtAddr::new(frame.start_address().as_u64()));
+        memory::with_kernel_memory(|mapper, _| {
+            mapper.unmap(page).expect("trampoline not mapped").1.flush()
+        });
+    }
+    unsafe { memory::deallocate_frame(frame) };
+}
+
+/// Returns the number of CPUs running the kernel.
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::SeqCst)
+}
+
+/// Maps the trampoline frame to the same virtual address, so that the AP
+/// keeps running when it enables paging.
+///
+/// Returns `false` if the frame was identity mapped already.
+fn identity_map(frame: PhysFrame) -> bool {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    memory::with_kernel_memory(|mapper, frame_allocator| {
+        match unsafe { mapper.identity_map(frame, flags, frame_allocator) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(MapToError::PageAlreadyMapped(mapped)) if mapped == frame => false,
+            Err(err) => panic!("failed to map the AP trampoline: {:?}", err),
+        }
+    })
+    .expect("kernel memory not installed")
+}
+
+/// Starts the AP with the given APIC ID and waits until it arrived in the
+/// kernel.
+fn start_ap(frame: PhysFrame, trampoline: VirtAddr, apic_id: u32) -> bool {
+    let base = frame.start_address().as_u64();
+    let offset = |symbol: &u8| unsafe {
+        symbol as *const u8 as u64 - &ap_trampoline_start as *const u8 as u64
+    };
+
+    let stack = Box::leak(vec![0u8; AP_STACK_SIZE].into_boxed_slice());
+    let stack_end = (stack.as_ptr() as u64 + AP_STACK_SIZE as u64) & !0xf;
+
+    let (data_offset, long_mode_offset) = unsafe {
+        (
+            offset(&ap_trampoline_data),
+            offset(&ap_trampoline_long_mode),
+        )
+    };
+    let data = TrampolineData {
+        // null, 64-bit code and data descriptors
+        gdt: [0, 0x00af_9a00_0000_ffff, 0x00cf_9200_0000_ffff],
+        gdtr_limit: 3 * 8 - 1,
+        gdtr_base: (base + data_offset) as u32,
+        far_jump_offset: (base + long_mode_offset) as u32,
+        far_jump_selector: TRAMPOLINE_CODE_SELECTOR,
+        page_table: Cr3::read().0.start_address().as_u64(),
+        stack: stack_end,
+        entry: ap_entry as usize as u64,
+        cpu_id: CPU_COUNT.load(Ordering::SeqCst) as u64,
+    };
+    assert!(data.page_table < 1 << 32, "kernel page table above 4 GiB");
+    unsafe {
+        let data_ptr = (trampoline + data_offset).as_mut_ptr::<TrampolineData>();
+        ptr::write_unaligned(data_ptr, data);
+    }
+
+    AP_ARRIVED.store(false, Ordering::SeqCst);
+    let vector = (base >> 12) as u32;
+    apic::local::send_ipi(apic_id, ICR_INIT);
+    delay_us(10_000);
+    for _ in 0..2 {
+        apic::local::send_ipi(apic_id, ICR_STARTUP | vector);
+        delay_us(200);
+    }
+
+    for _ in 0..100_000 {
+        if AP_ARRIVED.load(Ordering::SeqCst) {
+            return true;
+        }
+        delay_us(1);
+    }
+    false
+}
+
+/// Called by the trampoline on the AP's own stack.
+extern "C" fn ap_entry(cpu_id: usize) -> ! {
+    percpu::init(cpu_id);
+    context::init();
+    gdt::init_ap();
+    syscall::init();
+    interrupts::init_idt();
+    apic::init_ap();
+
+    CPU_COUNT.fetch_add(1, Ordering::SeqCst);
+    AP_ARRIVED.store(true, Ordering::SeqCst);
+
+    crate::kmain_ap(cpu_id);
+}
+
+/// Waits for roughly `us` microseconds, the time of an I/O port access.
+fn delay_us(us: usize) {
+    let mut port = Port::<u8>::new(0x80);
+    for _ in 0..us {
+        unsafe { port.write(0) };
+    }
+}
@@ -0,0 +1,9 @@
+//! # Synchronization between contexts
+//!
+//! The spin locks of the `spin` crate protect short critical sections.
+//! Contexts waiting for longer, e.g. for another process to exit, block on a
+//! `WaitQueue` instead.
+
+mod wait_queue;
+
+pub use wait_queue::WaitQueue;
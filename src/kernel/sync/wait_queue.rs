@@ -0,0 +1,49 @@
+use crate::kernel::context::{self, ContextId};
+use alloc::vec::Vec;
+use core::mem;
+use spin::Mutex;
+
+/// Contexts blocked until a condition, checked by themselves, holds.
+#[derive(Debug, Default)]
+pub struct WaitQueue {
+    waiters: Mutex<Vec<ContextId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Blocks the current context until `condition` returns `Some`.
+    ///
+    /// `condition` runs with the queue locked, so a `notify_all` after the
+    /// condition changed is never missed.
+    pub fn wait_until<T>(
+        &self,
+        reason: &'static str,
+        mut condition: impl FnMut() -> Option<T>,
+    ) -> T {
+        loop {
+            {
+                let mut waiters = self.waiters.lock();
+                if let Some(value) = condition() {
+                    return value;
+                }
+                let id = context::context_id().expect("no context to block");
+                waiters.push(id);
+                context::mark_blocked(reason);
+            }
+            context::wait();
+        }
+    }
+
+    /// Wakes up all waiting contexts, which check their condition again.
+    pub fn notify_all(&self) {
+        let waiters = mem::replace(&mut *self.waiters.lock(), Vec::new());
+        for id in waiters {
+            context::unblock(id);
+        }
+    }
+}
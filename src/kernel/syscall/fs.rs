@@ -0,0 +1,9 @@
+use super::validate::validate_slice;
+use super::{Error, Result, EBADF};
+use crate::kernel::process;
+
+pub fn write([fd, buf, len, ..]: [usize; 6]) -> Result<usize> {
+    let buf = validate_slice(buf as *const u8, len)?;
+    let file = process::file(fd).ok_or_else(|| Error::new(EBADF))?;
+    file.write(buf)
+}
@@ -0,0 +1,123 @@
+use super::{Error, Result, EBADF, EEXIST, EINVAL, EIO, ENODEV, ENOMEM};
+use super::{MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE};
+use crate::kernel::context;
+use crate::kernel::memory::address_space::{AddressSpace, MapError, USER_END, USER_START};
+use crate::kernel::memory::vma::VmaKind;
+use crate::kernel::process;
+use alloc::sync::Arc;
+use spin::Mutex;
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+pub fn mmap([addr, len, prot, flags, fd, offset]: [usize; 6]) -> Result<usize> {
+    let known = MAP_SHARED | MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS;
+    // there is no page cache to share file pages through yet
+    if flags & !known != 0 || flags & (MAP_SHARED | MAP_PRIVATE) != MAP_PRIVATE {
+        return Err(Error::new(EINVAL));
+    }
+    let page_flags = prot_flags(prot)?;
+    if len == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let kind = if flags & MAP_ANONYMOUS != 0 {
+        VmaKind::Anonymous
+    } else {
+        if offset as u64 % Page::<Size4KiB>::SIZE != 0 {
+            return Err(Error::new(EINVAL));
+        }
+        let file = process::file(fd).ok_or_else(|| Error::new(EBADF))?;
+        // only files with contents at an offset can be mapped
+        file.read_at(offset as u64, &mut [])
+            .map_err(|_| Error::new(ENODEV))?;
+        VmaKind::File {
+            file,
+            offset: offset as u64,
+        }
+    };
+
+    let addr_space = addr_space()?;
+    let mut addr_space = addr_space.lock();
+    let len = len as u64;
+    let start = if flags & MAP_FIXED != 0 {
+        let addr = user_addr(addr).ok_or_else(|| Error::new(EINVAL))?;
+        addr_space.munmap(addr, len).map_err(map_error)?;
+        addr_space.mmap(Some(addr), len, page_flags, kind)
+    } else {
+        // the hint is used if it is free
+        let hinted = user_addr(addr).and_then(|addr| {
+            addr_space
+                .mmap(Some(addr), len, page_flags, kind.clone())
+                .ok()
+        });
+        match hinted {
+            Some(start) => Ok(start),
+            None => addr_space.mmap(None, len, page_flags, kind),
+        }
+    };
+    start
+        .map(|start| start.as_u64() as usize)
+        .map_err(map_error)
+}
+
+pub fn munmap([addr, len, ..]: [usize; 6]) -> Result<usize> {
+    let addr = user_addr(addr).ok_or_else(|| Error::new(EINVAL))?;
+    addr_space()?
+        .lock()
+        .munmap(addr, len as u64)
+        .map_err(map_error)?;
+    Ok(0)
+}
+
+pub fn mprotect([addr, len, prot, ..]: [usize; 6]) -> Result<usize> {
+    let addr = user_addr(addr).ok_or_else(|| Error::new(EINVAL))?;
+    let flags = prot_flags(prot)?;
+    addr_space()?
+        .lock()
+        .mprotect(addr, len as u64, flags)
+        .map_err(map_error)?;
+    Ok(0)
+}
+
+/// Returns the address space of the calling context.
+fn addr_space() -> Result<Arc<Mutex<AddressSpace>>> {
+    context::with_current(|context| context.addr_space.clone())
+        .flatten()
+        .ok_or_else(|| Error::new(EINVAL))
+}
+
+/// Returns `addr` if it is a page-aligned user address.
+fn user_addr(addr: usize) -> Option<VirtAddr> {
+    let addr = addr as u64;
+    if addr % Page::<Size4KiB>::SIZE == 0 && USER_START <= addr && addr < USER_END {
+        Some(VirtAddr::new(addr))
+    } else {
+        None
+    }
+}
+
+/// Converts `PROT_*` flags to page table flags.
+///
+/// Present pages can always be read, so `PROT_NONE` is not supported.
+fn prot_flags(prot: usize) -> Result<PageTableFlags> {
+    if prot == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(Error::new(EINVAL));
+    }
+    let mut flags = PageTableFlags::empty();
+    if prot & PROT_WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Ok(flags)
+}
+
+fn map_error(err: MapError) -> Error {
+    match err {
+        MapError::OutOfMemory | MapError::NotMapped => Error::new(ENOMEM),
+        MapError::AlreadyMapped => Error::new(EEXIST),
+        MapError::ReadFailed => Error::new(EIO),
+        MapError::NotUserMemory | MapError::BadRange => Error::new(EINVAL),
+    }
+}
@@ -0,0 +1,177 @@
+//! # System calls
+//!
+//! User code enters the kernel with the `syscall` instruction, see the
+//! `toy_syscall` crate for the calling convention. The entry switches to the
+//! kernel stack of the context, saves the user registers in a `SyscallFrame`
+//! and calls the handler for the number in `rax` from `SYSCALLS`, with
+//! interrupts enabled. `fork`, which needs all user registers, gets the
+//! frame instead. Errors are returned as negated error codes.
+
+pub use toy_syscall::error::*;
+pub use toy_syscall::flag::*;
+pub use toy_syscall::number::*;
+
+use crate::kernel::devices::gdt;
+use x86_64::registers::model_specific::{Efer, EferFlags, Msr};
+use x86_64::VirtAddr;
+
+mod fs;
+mod memory;
+mod process;
+pub mod validate;
+
+/// The MSR holding the segments for `syscall` and `sysret`.
+const STAR: u32 = 0xc000_0081;
+/// The MSR holding the address of the system call entry.
+const LSTAR: u32 = 0xc000_0082;
+/// The MSR holding the flags cleared on entry.
+const SFMASK: u32 = 0xc000_0084;
+
+/// The flags cleared on entry: trap, interrupt, direction and alignment check.
+const ENTRY_FLAGS_MASK: u64 = 0x4_0700;
+
+/// The handler of a system call, called with the six argument registers.
+type Handler = fn([usize; 6]) -> Result<usize>;
+
+/// The handlers, indexed by system call number.
+static SYSCALLS: [Option<Handler>; 10] = [
+    Some(fs::write),            // SYS_WRITE
+    Some(process::exit),        // SYS_EXIT
+    Some(process::sched_yield), // SYS_YIELD
+    Some(process::getpid),      // SYS_GETPID
+    Some(process::sleep),       // SYS_SLEEP
+    Some(process::waitpid),     // SYS_WAITPID
+    None,                       // SYS_FORK, see `syscall_handler`
+    Some(memory::mmap),         // SYS_MMAP
+    Some(memory::munmap),       // SYS_MUNMAP
+    Some(memory::mprotect),     // SYS_MPROTECT
+];
+
+/// The user registers saved by `syscall_entry`.
+///
+/// `rcx` and `r11` are clobbered by `syscall`, so this is the complete state
+/// of user code, from which `usermode::enter` can start it.
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct SyscallFrame {
+    pub rax: usize,
+    pub rdi: usize,
+    pub rsi: usize,
+    pub rdx: usize,
+    pub r10: usize,
+    pub r8: usize,
+    pub r9: usize,
+    pub rbx: usize,
+    pub rbp: usize,
+    pub r12: usize,
+    pub r13: usize,
+    pub r14: usize,
+    pub r15: usize,
+    pub rflags: usize,
+    pub rip: usize,
+    pub rsp: usize,
+}
+
+impl SyscallFrame {
+    /// The registers of user code starting at `ip` with the stack pointer
+    /// `sp`. All other registers are cleared.
+    pub fn new(ip: VirtAddr, sp: VirtAddr) -> Self {
+        SyscallFrame {
+            rip: ip.as_u64() as usize,
+            rsp: sp.as_u64() as usize,
+            ..SyscallFrame::default()
+        }
+    }
+}
+
+global_asm!(
+    r#"
+.intel_syntax noprefix
+
+# `syscall` leaves the user's rip in rcx and rflags in r11
+.global syscall_entry
+syscall_entry:
+    swapgs
+    # switch to the kernel stack, see `PerCpu`
+    mov qword ptr gs:[16], rsp
+    mov rsp, qword ptr gs:[8]
+
+    # the `SyscallFrame`
+    push qword ptr gs:[16]
+    push rcx
+    push r11
+    push r15
+    push r14
+    push r13
+    push r12
+    push rbp
+    push rbx
+    push r9
+    push r8
+    push r10
+    push rdx
+    push rsi
+    push rdi
+    push rax
+
+    sti
+    mov rdi, rsp
+    call syscall_handler
+    cli
+
+    pop rax
+    pop rdi
+    pop rsi
+    pop rdx
+    pop r10
+    pop r8
+    pop r9
+    pop rbx
+    pop rbp
+    pop r12
+    pop r13
+    pop r14
+    pop r15
+    pop r11
+    pop rcx
+    pop rsp
+    swapgs
+    sysretq
+
+.att_syntax prefix
+"#
+);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// Enables the `syscall` instruction on the executing CPU.
+///
+/// Must be called once per CPU, after its GDT was loaded.
+pub fn init() {
+    // `sysret` loads the user data segment from the base + 8 and the user
+    // code segment from the base + 16
+    let sysret_base = u64::from(gdt::USER_DATA_SELECTOR.0 - 8);
+    let syscall_base = u64::from(gdt::KERNEL_CODE_SELECTOR.0);
+    unsafe {
+        Msr::new(STAR).write(sysret_base << 48 | syscall_base << 32);
+        Msr::new(LSTAR).write(syscall_entry as usize as u64);
+        Msr::new(SFMASK).write(ENTRY_FLAGS_MASK);
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+}
+
+/// Calls the handler of a system call and stores its result in `frame.rax`.
+#[no_mangle]
+extern "C" fn syscall_handler(frame: &mut SyscallFrame) {
+    let args = [
+        frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+    ];
+    let result = match SYSCALLS.get(frame.rax) {
+        Some(Some(handler)) => handler(args),
+        _ if frame.rax == SYS_FORK => process::fork(frame),
+        _ => Err(Error::new(ENOSYS)),
+    };
+    frame.rax = Error::mux(result);
+}
@@ -0,0 +1,58 @@
+use super::validate::validate_slice_mut;
+use super::{Error, Result, SyscallFrame, EINVAL, ESRCH, WNOHANG};
+use crate::kernel::context;
+use crate::kernel::interrupts::{self, TICKS_PER_SECOND};
+use crate::kernel::process::{self, ProcessId};
+
+pub fn exit([status, ..]: [usize; 6]) -> Result<usize> {
+    process::exit(status);
+}
+
+/// Not in `SYSCALLS`, since the child needs the registers of the caller.
+pub fn fork(frame: &SyscallFrame) -> Result<usize> {
+    process::fork(frame).map(|id| id.as_usize())
+}
+
+pub fn sched_yield(_: [usize; 6]) -> Result<usize> {
+    context::yield_now();
+    Ok(0)
+}
+
+pub fn getpid(_: [usize; 6]) -> Result<usize> {
+    process::process_id()
+        .map(|id| id.as_usize())
+        .ok_or_else(|| Error::new(ESRCH))
+}
+
+pub fn sleep([ms, ..]: [usize; 6]) -> Result<usize> {
+    // round up, sleeping too long is fine
+    let ticks = (ms as u64).saturating_mul(TICKS_PER_SECOND);
+    let ticks = ticks / 1000 + (ticks % 1000 != 0) as u64;
+    context::sleep_until(interrupts::ticks().saturating_add(ticks));
+    Ok(0)
+}
+
+pub fn waitpid([pid, status, options, ..]: [usize; 6]) -> Result<usize> {
+    let pid = match pid as isize {
+        -1 => None,
+        pid if pid > 0 => Some(ProcessId::new(pid as usize)),
+        _ => return Err(Error::new(EINVAL)),
+    };
+    if options & !WNOHANG != 0 {
+        return Err(Error::new(EINVAL));
+    }
+    let status = match status {
+        0 => None,
+        ptr => Some(validate_slice_mut(ptr as *mut usize, 1)?),
+    };
+
+    match process::waitpid(pid, options & WNOHANG != 0)? {
+        Some((pid, exit_status)) => {
+            if let Some(status) = status {
+                status[0] = exit_status;
+            }
+            Ok(pid.as_usize())
+        }
+        None => Ok(0),
+    }
+}
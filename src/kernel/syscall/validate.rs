@@ -0,0 +1,77 @@
+//! Checks of pointers passed by user code.
+
+use super::{Error, Result, EFAULT, EIO, ENOMEM};
+use crate::kernel::context;
+use crate::kernel::memory::address_space::{MapError, USER_END, USER_START};
+use core::{mem, slice};
+use x86_64::structures::paging::{Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+/// Checks that `len` bytes at `address` are mapped user memory of the current
+/// context, and writable if `writable` is set.
+fn validate(address: usize, len: usize, writable: bool) -> Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = address.checked_add(len).ok_or_else(|| Error::new(EFAULT))?;
+    if (address as u64) < USER_START || end as u64 > USER_END {
+        return Err(Error::new(EFAULT));
+    }
+
+    let addr_space = context::with_current(|context| context.addr_space.clone())
+        .flatten()
+        .ok_or_else(|| Error::new(EFAULT))?;
+    let mut addr_space = addr_space.lock();
+
+    let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if writable {
+        required |= PageTableFlags::WRITABLE;
+    }
+    let first = Page::containing_address(VirtAddr::new(address as u64));
+    let last = Page::containing_address(VirtAddr::new(end as u64 - 1));
+    for page in Page::range_inclusive(first, last) {
+        // map untouched and copy shared pages up front instead of faulting in
+        // the kernel
+        addr_space
+            .resolve_fault(page, writable)
+            .map_err(|err| match err {
+                MapError::OutOfMemory => Error::new(ENOMEM),
+                _ => Error::new(EIO),
+            })?;
+        match addr_space.page_flags(page) {
+            Some(flags) if flags.contains(required) => (),
+            _ => return Err(Error::new(EFAULT)),
+        }
+    }
+    Ok(())
+}
+
+/// Convert a pointer and length to a slice, if valid
+///
+/// The slice must not be used after the system call returns. Until then it
+/// stays mapped, since only the calling thread may change its address space,
+/// see `memory::address_space`.
+pub fn validate_slice<T>(ptr: *const T, len: usize) -> Result<&'static [T]> {
+    let size = len
+        .checked_mul(mem::size_of::<T>())
+        .ok_or_else(|| Error::new(EFAULT))?;
+    if ptr as usize % mem::align_of::<T>() != 0 {
+        return Err(Error::new(EFAULT));
+    }
+    validate(ptr as usize, size, false)?;
+    Ok(unsafe { slice::from_raw_parts(ptr, len) })
+}
+
+/// Convert a pointer and length to a mutable slice, if valid
+///
+/// Like `validate_slice`, the slice must not outlive the system call.
+pub fn validate_slice_mut<T>(ptr: *mut T, len: usize) -> Result<&'static mut [T]> {
+    let size = len
+        .checked_mul(mem::size_of::<T>())
+        .ok_or_else(|| Error::new(EFAULT))?;
+    if ptr as usize % mem::align_of::<T>() != 0 {
+        return Err(Error::new(EFAULT));
+    }
+    validate(ptr as usize, size, true)?;
+    Ok(unsafe { slice::from_raw_parts_mut(ptr, len) })
+}
@@ -0,0 +1,53 @@
+//! # User mode
+//!
+//! User code runs in ring 3 with the user segments of the GDT, in the address
+//! space of its context. The kernel is entered again through interrupts and
+//! exceptions, which switch to the context's kernel stack set in the TSS.
+
+use crate::kernel::devices::gdt;
+use crate::kernel::syscall::SyscallFrame;
+
+/// The flags user code always runs with: interrupts enabled.
+const USER_RFLAGS: usize = 0x202;
+/// The flags user code may change, the same `sysretq` keeps.
+const USER_RFLAGS_MASK: usize = 0x3c_7fd7;
+
+/// Leaves the kernel and continues user code with the registers in `frame`.
+///
+/// This function is unsafe because the current context's address space must
+/// map `frame.rip` and `frame.rsp` for user code, and the TSS must point to
+/// the context's kernel stack.
+pub unsafe fn enter(frame: &SyscallFrame) -> ! {
+    let rflags = (frame.rflags & USER_RFLAGS_MASK) | USER_RFLAGS;
+    asm!(
+        "cli",
+        "push rcx",
+        "push qword ptr [rdi + 120]",
+        "push rax",
+        "push rdx",
+        "push qword ptr [rdi + 112]",
+        // user code gets its own GS base
+        "swapgs",
+        "mov rax, [rdi]",
+        "mov rsi, [rdi + 16]",
+        "mov rdx, [rdi + 24]",
+        "mov r10, [rdi + 32]",
+        "mov r8, [rdi + 40]",
+        "mov r9, [rdi + 48]",
+        "mov rbx, [rdi + 56]",
+        "mov rbp, [rdi + 64]",
+        "mov r12, [rdi + 72]",
+        "mov r13, [rdi + 80]",
+        "mov r14, [rdi + 88]",
+        "mov r15, [rdi + 96]",
+        "xor ecx, ecx",
+        "xor r11d, r11d",
+        "mov rdi, [rdi + 8]",
+        "iretq",
+        in("rdi") frame as *const SyscallFrame,
+        in("rdx") u64::from(gdt::USER_CODE_SELECTOR.0),
+        in("rcx") u64::from(gdt::USER_DATA_SELECTOR.0),
+        in("rax") rflags,
+        options(noreturn)
+    );
+}
@@ -1,10 +1,17 @@
 #![no_std]
+#![cfg_attr(test, no_main)]
 #![feature(abi_x86_interrupt)]
+#![feature(asm)]
+#![feature(global_asm)]
 #![feature(alloc_error_handler)]
 #![feature(const_fn)]
 #![feature(const_in_array_repeat_expressions)]
 #![feature(wake_trait)]
 #![feature(thread_local)]
+#![feature(panic_info_message)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 extern crate rlibc;
@@ -12,9 +19,12 @@ extern crate rlibc;
 pub mod kernel;
 pub mod qemu;
 pub mod task;
+pub mod testing;
 
 pub use bootloader::BootInfo;
-use kernel::memory::BootInfoFrameAllocator;
+use kernel::devices::apic::ApicInfo;
+use kernel::memory::bitmap::BitmapFrameAllocator;
+pub use testing::test_runner;
 use x86_64::VirtAddr;
 
 /// This is the kernel entry point for the primary CPU.
@@ -24,14 +34,32 @@ pub fn kmain(boot_info: &'static BootInfo) {
     kernel::interrupts::init_idt();
     unsafe { kernel::interrupts::PICS.lock().initialize() };
 
-    x86_64::instructions::interrupts::enable();
-
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { kernel::memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
 
     kernel::allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
+    kernel::memory::install(mapper, frame_allocator);
+    kernel::percpu::init(0);
+    kernel::context::init();
+    kernel::syscall::init();
+
+    if let Err(err) = kernel::acpi::init() {
+        println!("WARNING: failed to parse ACPI tables: {:?}", err);
+    }
+    kernel::devices::apic::init(&ApicInfo::detect());
+
+    kernel::interrupts::init_irqs();
+    x86_64::instructions::interrupts::enable();
+
+    kernel::smp::init();
+}
+
+/// Returns the id of the executing CPU; the bootstrap processor is CPU 0.
+pub fn cpu_id() -> usize {
+    kernel::percpu::cpu_id()
 }
 
 /// This is the main kernel entry point for secondary CPUs
@@ -58,3 +86,13 @@ macro_rules! userspace_entrypoint {
         }
     };
 }
+
+#[cfg(test)]
+userspace_entrypoint!(test_kernel_main);
+
+/// Entry point for `cargo test`
+#[cfg(test)]
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
@@ -1,6 +1,9 @@
 #![no_std]
 #![no_main]
 #![feature(box_syntax)]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 
@@ -11,6 +14,9 @@ use toy_os::userspace_entrypoint;
 userspace_entrypoint!(userspace_main);
 
 fn userspace_main() -> ! {
+    #[cfg(test)]
+    test_main();
+
     println!("Hello World!");
 
     let mut executor = Executor::new();
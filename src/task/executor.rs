@@ -1,10 +1,11 @@
 use super::{Task, TaskId};
+use crate::kernel::allocator::slab::SlabBox;
 use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
 use core::task::{Context, Poll, Waker};
 use crossbeam_queue::ArrayQueue;
 
 pub struct Executor {
-    tasks: BTreeMap<TaskId, Task>,
+    tasks: BTreeMap<TaskId, SlabBox<Task>>,
     task_queue: Arc<ArrayQueue<TaskId>>,
     waker_cache: BTreeMap<TaskId, Waker>,
 }
@@ -20,7 +21,7 @@ impl Executor {
 
     pub fn spawn(&mut self, task: Task) {
         let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
+        if self.tasks.insert(task.id, task.into_slab()).is_some() {
             panic!("task with same ID already in tasks");
         }
         self.task_queue.push(task_id).expect("queue full");
@@ -33,6 +34,11 @@ impl Executor {
         }
     }
 
+    /// Polls tasks until none of them is ready anymore, without sleeping.
+    pub fn run_until_idle(&mut self) {
+        self.run_ready_tasks();
+    }
+
     fn run_ready_tasks(&mut self) {
         // destructure `self` to avoid borrow checker errors
         let Self {
@@ -1,3 +1,4 @@
+use crate::kernel::allocator::slab::{ObjectCache, SlabBox};
 use alloc::boxed::Box;
 use core::{
     future::Future,
@@ -14,6 +15,9 @@ pub struct Task {
     future: Pin<Box<dyn Future<Output = ()>>>,
 }
 
+/// The cache the executors keep their tasks in.
+static TASKS: ObjectCache<Task> = ObjectCache::new("task");
+
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
         Task {
@@ -22,17 +26,43 @@ impl Task {
         }
     }
 
+    /// Moves the task into an object of the task cache.
+    fn into_slab(self) -> SlabBox<Task> {
+        TASKS.alloc(self)
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        CURRENT_TASK.store(self.id.0, Ordering::Relaxed);
+        let poll = self.future.as_mut().poll(context);
+        CURRENT_TASK.store(NO_TASK, Ordering::Relaxed);
+        poll
     }
 }
 
+/// Marks `CURRENT_TASK` as empty.
+const NO_TASK: u64 = u64::MAX;
+
+/// The ID of the task that is currently being polled.
+static CURRENT_TASK: AtomicU64 = AtomicU64::new(NO_TASK);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    /// Returns the ID of the task that is currently being polled, if any.
+    pub fn current() -> Option<TaskId> {
+        match CURRENT_TASK.load(Ordering::Relaxed) {
+            NO_TASK => None,
+            id => Some(TaskId(id)),
+        }
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
 }
@@ -0,0 +1,147 @@
+//! # In-kernel test framework
+//!
+//! Tests are collected with `#[test_case]` and run by `test_runner` under QEMU.
+//! Results are printed over serial and QEMU is exited through the
+//! `isa-debug-exit` device, see `Cargo.toml`. Fixtures shared by the tests
+//! that run user code are in `user`.
+
+use crate::kernel::devices::serial::SERIAL1;
+use crate::kernel::interrupts;
+use crate::qemu::{exit_qemu, QemuExitCode};
+use crate::{serial_print, serial_println};
+use core::any::type_name;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use uart_16550::SerialPort;
+
+pub mod user;
+
+/// The timeout for tests that do not specify one.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Set while `test_runner` runs tests.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Set while a test that is expected to panic runs.
+static SHOULD_PANIC: AtomicBool = AtomicBool::new(false);
+/// The tick after which the current test is considered hung, zero if none.
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+pub trait Testable {
+    /// Runs the test, printing its name and result over serial.
+    fn run(&self);
+
+    /// Returns the number of seconds after which the test fails.
+    fn timeout_secs(&self) -> u64 {
+        DEFAULT_TIMEOUT_SECS
+    }
+
+    /// Returns whether the test passes by panicking.
+    fn should_panic(&self) -> bool {
+        false
+    }
+}
+
+impl<T> Testable for T
+where
+    T: Fn(),
+{
+    fn run(&self) {
+        serial_print!("{}...\t", type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+/// A test with a timeout other than `DEFAULT_TIMEOUT_SECS`.
+///
+/// Register it as `#[test_case] static NAME: WithTimeout = ...`.
+pub struct WithTimeout {
+    pub name: &'static str,
+    pub timeout_secs: u64,
+    pub test: fn(),
+}
+
+impl Testable for WithTimeout {
+    fn run(&self) {
+        serial_print!("{}...\t", self.name);
+        (self.test)();
+        serial_println!("[ok]");
+    }
+
+    fn timeout_secs(&self) -> u64 {
+        self.timeout_secs
+    }
+}
+
+/// A test that passes only if it panics.
+///
+/// Since the kernel cannot unwind, it always runs after all other tests of its
+/// binary, and a binary can contain at most one of them.
+pub struct ShouldPanic {
+    pub name: &'static str,
+    pub test: fn(),
+}
+
+impl Testable for ShouldPanic {
+    fn run(&self) {
+        serial_print!("{}...\t", self.name);
+        SHOULD_PANIC.store(true, Ordering::SeqCst);
+        (self.test)();
+        SHOULD_PANIC.store(false, Ordering::SeqCst);
+        serial_println!("[failed]\n");
+        serial_println!("Error: test did not panic\n");
+        exit_qemu(QemuExitCode::Failed);
+    }
+
+    fn should_panic(&self) -> bool {
+        true
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    RUNNING.store(true, Ordering::SeqCst);
+    serial_println!("Running {} tests", tests.len());
+
+    let should_panic = tests.iter().filter(|test| test.should_panic()).count();
+    if should_panic > 1 {
+        serial_println!("Error: at most one should_panic test per binary is supported\n");
+        exit_qemu(QemuExitCode::Failed);
+    }
+
+    let normal = tests.iter().filter(|test| !test.should_panic());
+    let last = tests.iter().filter(|test| test.should_panic());
+    for test in normal.chain(last) {
+        let timeout = test.timeout_secs() * interrupts::TICKS_PER_SECOND;
+        DEADLINE.store(interrupts::ticks() + timeout, Ordering::SeqCst);
+        test.run();
+        DEADLINE.store(0, Ordering::SeqCst);
+    }
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// Returns whether the kernel is running tests.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+/// Called on panics while tests are running.
+///
+/// Returns whether the panic was expected by the running test.
+pub(crate) fn panic_expected() -> bool {
+    SHOULD_PANIC.load(Ordering::SeqCst)
+}
+
+/// Called by the timer interrupt handler; fails the running test once its
+/// deadline has passed.
+pub(crate) fn check_timeout(ticks: u64) {
+    let deadline = DEADLINE.load(Ordering::Relaxed);
+    if deadline != 0 && ticks > deadline {
+        // the hung test may hold the port on another CPU, so never wait for it
+        let _ = match SERIAL1.try_lock() {
+            Some(mut serial) => serial.write_str("[timeout]\n\n"),
+            None => unsafe { SerialPort::new(0x3f8) }.write_str("[timeout]\n\n"),
+        };
+        exit_qemu(QemuExitCode::Failed);
+    }
+}
@@ -0,0 +1,101 @@
+//! Fixtures for tests that run code in user mode: an assembler for the few
+//! instructions the test programs need, and helpers to inspect address spaces
+//! and to run processes.
+
+use crate::kernel::memory::{self, address_space::AddressSpace};
+use crate::kernel::process::{self, ProcessId};
+use crate::kernel::syscall::SYS_EXIT;
+use alloc::vec::Vec;
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+pub fn addr(address: u64) -> VirtAddr {
+    VirtAddr::new(address)
+}
+
+pub fn page(address: u64) -> Page {
+    Page::containing_address(addr(address))
+}
+
+/// Reads the `u64` at `address`, which must be mapped in `space`.
+pub fn read_u64(space: &mut AddressSpace, address: u64) -> u64 {
+    let phys = space.translate(addr(address)).expect("not mapped");
+    unsafe { *memory::phys_to_virt(phys).as_ptr::<u64>() }
+}
+
+/// A tiny assembler for the test programs.
+#[derive(Default)]
+pub struct Program(Vec<u8>);
+
+impl Program {
+    /// Appends raw machine code.
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn mov_eax(self, value: u32) -> Self {
+        self.bytes(&[0xb8]).bytes(&value.to_le_bytes())
+    }
+
+    pub fn mov_rax(self, value: u64) -> Self {
+        self.bytes(&[0x48, 0xb8]).bytes(&value.to_le_bytes())
+    }
+
+    pub fn mov_edi(self, value: u32) -> Self {
+        self.bytes(&[0xbf]).bytes(&value.to_le_bytes())
+    }
+
+    pub fn mov_esi(self, value: u32) -> Self {
+        self.bytes(&[0xbe]).bytes(&value.to_le_bytes())
+    }
+
+    pub fn mov_rsi(self, value: u64) -> Self {
+        self.bytes(&[0x48, 0xbe]).bytes(&value.to_le_bytes())
+    }
+
+    pub fn mov_edx(self, value: u32) -> Self {
+        self.bytes(&[0xba]).bytes(&value.to_le_bytes())
+    }
+
+    pub fn mov_r10d(self, value: u32) -> Self {
+        self.bytes(&[0x41, 0xba]).bytes(&value.to_le_bytes())
+    }
+
+    /// Calls `number` with the arguments already in their registers.
+    pub fn syscall(self, number: usize) -> Self {
+        self.mov_eax(number as u32).bytes(&[0x0f, 0x05])
+    }
+
+    /// Exits with the result of the last system call.
+    pub fn exit_with_result(self) -> Self {
+        // mov rdi, rax
+        self.bytes(&[0x48, 0x89, 0xc7]).syscall(SYS_EXIT)
+    }
+
+    /// Returns the machine code assembled so far.
+    pub fn code(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Starts a process running the code at `entry` in `space`, with the stack
+/// pointer at `stack_end`.
+pub fn start(space: AddressSpace, entry: u64, stack_end: u64) -> ProcessId {
+    process::spawn_user("user", space, addr(entry), addr(stack_end)).expect("could not spawn")
+}
+
+/// Waits for the child `id` to exit and returns its status.
+pub fn wait(id: ProcessId) -> usize {
+    let (pid, status) = process::waitpid(Some(id), false)
+        .expect("no such child")
+        .expect("did not wait");
+    assert_eq!(pid, id);
+    status
+}
+
+/// Runs the code at `entry` in `space` like `start` and returns its exit
+/// status.
+pub fn run(space: AddressSpace, entry: u64, stack_end: u64) -> usize {
+    wait(start(space, entry, stack_end))
+}
@@ -0,0 +1,123 @@
+//! The raw `syscall` instruction, for every number of arguments.
+
+use crate::error::{Error, Result};
+
+pub unsafe fn syscall0(number: usize) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
+
+pub unsafe fn syscall1(number: usize, a: usize) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
+
+pub unsafe fn syscall2(number: usize, a: usize, b: usize) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a,
+        in("rsi") b,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
+
+pub unsafe fn syscall3(number: usize, a: usize, b: usize, c: usize) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a,
+        in("rsi") b,
+        in("rdx") c,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
+
+pub unsafe fn syscall4(number: usize, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a,
+        in("rsi") b,
+        in("rdx") c,
+        in("r10") d,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
+
+pub unsafe fn syscall5(
+    number: usize,
+    a: usize,
+    b: usize,
+    c: usize,
+    d: usize,
+    e: usize,
+) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a,
+        in("rsi") b,
+        in("rdx") c,
+        in("r10") d,
+        in("r8") e,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
+
+pub unsafe fn syscall6(
+    number: usize,
+    a: usize,
+    b: usize,
+    c: usize,
+    d: usize,
+    e: usize,
+    f: usize,
+) -> Result<usize> {
+    let result: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a,
+        in("rsi") b,
+        in("rdx") c,
+        in("r10") d,
+        in("r8") e,
+        in("r9") f,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    Error::demux(result)
+}
@@ -0,0 +1,87 @@
+//! Wrappers for the system calls.
+
+use crate::arch::*;
+use crate::error::Result;
+use crate::number::*;
+
+/// Writes `buf` to the file `fd` and returns the number of bytes written.
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize> {
+    unsafe { syscall3(SYS_WRITE, fd, buf.as_ptr() as usize, buf.len()) }
+}
+
+/// Terminates the calling process with `status`.
+pub fn exit(status: usize) -> ! {
+    unsafe {
+        let _ = syscall1(SYS_EXIT, status);
+    }
+    unreachable!("exit returned");
+}
+
+/// Starts a copy of the calling process and returns the ID of the child, or 0
+/// in the child.
+pub fn fork() -> Result<usize> {
+    unsafe { syscall0(SYS_FORK) }
+}
+
+/// Lets other contexts run.
+pub fn sched_yield() -> Result<usize> {
+    unsafe { syscall0(SYS_YIELD) }
+}
+
+/// Returns the ID of the calling process.
+pub fn getpid() -> Result<usize> {
+    unsafe { syscall0(SYS_GETPID) }
+}
+
+/// Blocks the calling context for at least `ms` milliseconds.
+pub fn sleep(ms: usize) -> Result<usize> {
+    unsafe { syscall1(SYS_SLEEP, ms) }
+}
+
+/// Waits for the child `pid`, or any child if `pid` is -1, to exit and returns
+/// its ID, storing its exit status in `status`.
+///
+/// With `WNOHANG` in `options`, returns 0 if no child exited yet.
+pub fn waitpid(pid: isize, status: &mut usize, options: usize) -> Result<usize> {
+    unsafe {
+        syscall3(
+            SYS_WAITPID,
+            pid as usize,
+            status as *mut usize as usize,
+            options,
+        )
+    }
+}
+
+/// Maps `len` bytes of zeroed memory, or of the file `fd` from `offset` on, and
+/// returns their address, see `MAP_*` and `PROT_*`.
+///
+/// `addr` is only a hint unless `flags` contains `MAP_FIXED`.
+///
+/// This function is unsafe because a `MAP_FIXED` mapping replaces whatever
+/// was mapped at `addr` before.
+pub unsafe fn mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> Result<usize> {
+    syscall6(SYS_MMAP, addr, len, prot, flags, fd, offset)
+}
+
+/// Unmaps the `len` bytes at `addr`.
+///
+/// This function is unsafe because the memory must not be used anymore.
+pub unsafe fn munmap(addr: usize, len: usize) -> Result<usize> {
+    syscall2(SYS_MUNMAP, addr, len)
+}
+
+/// Changes the protection of the `len` bytes at `addr` to `prot`.
+///
+/// This function is unsafe because the memory may not be accessible in the
+/// ways it was before.
+pub unsafe fn mprotect(addr: usize, len: usize, prot: usize) -> Result<usize> {
+    syscall3(SYS_MPROTECT, addr, len, prot)
+}
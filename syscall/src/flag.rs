@@ -0,0 +1,20 @@
+//! Flags passed to system calls.
+
+/// `waitpid`: return 0 instead of blocking if no child exited yet.
+pub const WNOHANG: usize = 1;
+
+/// `mmap`, `mprotect`: the pages can be read.
+pub const PROT_READ: usize = 1;
+/// `mmap`, `mprotect`: the pages can be written.
+pub const PROT_WRITE: usize = 2;
+/// `mmap`, `mprotect`: the pages can be executed.
+pub const PROT_EXEC: usize = 4;
+
+/// `mmap`: writes are seen by other mappings of the file. Not supported yet.
+pub const MAP_SHARED: usize = 0x01;
+/// `mmap`: writes are only seen by the calling process.
+pub const MAP_PRIVATE: usize = 0x02;
+/// `mmap`: map exactly at `addr`, replacing what was mapped there.
+pub const MAP_FIXED: usize = 0x10;
+/// `mmap`: map zeroed memory instead of a file; `fd` and `offset` are ignored.
+pub const MAP_ANONYMOUS: usize = 0x20;
@@ -0,0 +1,25 @@
+//! # toy_os system calls
+//!
+//! The system call numbers and error codes shared by the kernel and user
+//! programs, and wrappers that invoke the calls from user code.
+//!
+//! A call is made with the `syscall` instruction: the number goes in `rax`,
+//! the arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`. The result is
+//! returned in `rax`, with errors encoded as negated error codes (see
+//! `Error::mux`). `rcx` and `r11` are clobbered, all other registers are
+//! preserved.
+
+#![no_std]
+#![feature(asm)]
+
+pub use self::arch::*;
+pub use self::call::*;
+pub use self::error::*;
+pub use self::flag::*;
+pub use self::number::*;
+
+mod arch;
+mod call;
+pub mod error;
+pub mod flag;
+pub mod number;
@@ -0,0 +1,27 @@
+//! The system call numbers.
+
+/// `write(fd, buf, len)`: writes `len` bytes from `buf` to the file `fd`.
+pub const SYS_WRITE: usize = 0;
+/// `exit(status)`: terminates the calling process with `status`.
+pub const SYS_EXIT: usize = 1;
+/// `yield()`: lets other contexts run.
+pub const SYS_YIELD: usize = 2;
+/// `getpid()`: returns the ID of the calling process.
+pub const SYS_GETPID: usize = 3;
+/// `sleep(ms)`: blocks the calling context for at least `ms` milliseconds.
+pub const SYS_SLEEP: usize = 4;
+/// `waitpid(pid, status, options)`: waits for the child `pid`, or any child if
+/// `pid` is -1, to exit, stores its exit status in `status` unless it is null
+/// and returns its ID.
+pub const SYS_WAITPID: usize = 5;
+/// `fork()`: starts a copy of the calling process and returns the ID of the
+/// child, or 0 in the child.
+pub const SYS_FORK: usize = 6;
+/// `mmap(addr, len, prot, flags, fd, offset)`: maps `len` bytes of zeroed
+/// memory, or of the file `fd` from `offset` on, and returns their address.
+pub const SYS_MMAP: usize = 7;
+/// `munmap(addr, len)`: unmaps the `len` bytes at `addr`.
+pub const SYS_MUNMAP: usize = 8;
+/// `mprotect(addr, len, prot)`: changes the protection of the `len` bytes at
+/// `addr`.
+pub const SYS_MPROTECT: usize = 9;
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::kernel::acpi;
+use toy_os::{hlt_loop, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+#[test_case]
+fn tables_found() {
+    let acpi = acpi::get().expect("ACPI tables not parsed");
+    assert!(acpi.find_table(b"APIC").is_some());
+    assert!(acpi.find_table(b"FACP").is_some());
+    assert!(acpi.find_table(b"NONE").is_none());
+}
+
+#[test_case]
+fn madt_lists_cpus_and_io_apic() {
+    let madt = acpi::get().unwrap().madt.as_ref().unwrap();
+    assert!(madt.processors.iter().any(|cpu| cpu.usable));
+    assert_eq!(madt.io_apics.len(), 1);
+    // QEMU connects the PIT to GSI 2
+    assert!(madt.overrides.iter().any(|o| o.irq == 0 && o.gsi == 2));
+}
+
+#[test_case]
+fn fadt_parsed() {
+    let fadt = acpi::get().unwrap().fadt.as_ref().unwrap();
+    assert_ne!(fadt.pm1a_control_block, 0);
+    assert_ne!(fadt.dsdt.as_u64(), 0);
+}
+
+#[test_case]
+fn hpet_parsed() {
+    let hpet = acpi::get().unwrap().hpet.expect("QEMU provides an HPET");
+    assert!(hpet.comparators >= 3);
+    assert_eq!(hpet.base_address.address, 0xfed0_0000);
+}
+
+#[test_case]
+fn s5_sleep_type_found() {
+    let dsdt = acpi::get().unwrap().dsdt().expect("no valid DSDT");
+    assert!(acpi::dsdt::sleep_type(&dsdt, 5).is_some());
+}
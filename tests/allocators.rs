@@ -0,0 +1,222 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::alloc::{GlobalAlloc, Layout};
+use toy_os::kernel::allocator::{
+    bump::BumpAllocator, linked_list::LinkedListAllocator, slab::ObjectCache, slab::SlabCache,
+    stats::AllocatorStats, Locked,
+};
+use toy_os::kernel::memory;
+use toy_os::kernel::memory::buddy::{BuddyFrameAllocator, MAX_ORDER};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::{FrameDeallocator, PhysFrame};
+use x86_64::PhysAddr;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const TEST_HEAP_SIZE: usize = 16 * 1024;
+
+#[repr(align(4096))]
+struct TestHeap([u8; TEST_HEAP_SIZE]);
+
+static mut BUMP_HEAP: TestHeap = TestHeap([0; TEST_HEAP_SIZE]);
+static mut LINKED_LIST_HEAP: TestHeap = TestHeap([0; TEST_HEAP_SIZE]);
+
+#[test_case]
+fn bump_allocator_resets_when_empty() {
+    let allocator = Locked::new(BumpAllocator::new());
+    unsafe {
+        allocator
+            .lock()
+            .init(BUMP_HEAP.0.as_ptr() as usize, TEST_HEAP_SIZE)
+    };
+
+    let layout = Layout::from_size_align(TEST_HEAP_SIZE / 2, 8).unwrap();
+    let first = unsafe { allocator.alloc(layout) };
+    let second = unsafe { allocator.alloc(layout) };
+    assert!(!first.is_null() && !second.is_null());
+    assert!(unsafe { allocator.alloc(layout) }.is_null());
+
+    unsafe {
+        allocator.dealloc(first, layout);
+        allocator.dealloc(second, layout);
+    }
+    assert_eq!(unsafe { allocator.alloc(layout) }, first);
+}
+
+#[test_case]
+fn linked_list_allocator_coalesces() {
+    let allocator = Locked::new(LinkedListAllocator::new());
+    let heap_start = unsafe { LINKED_LIST_HEAP.0.as_ptr() as usize };
+    unsafe { allocator.lock().init(heap_start, TEST_HEAP_SIZE) };
+
+    // fragment the whole heap, then free it out of order
+    let layout = Layout::from_size_align(TEST_HEAP_SIZE / 8, 8).unwrap();
+    let mut blocks = [core::ptr::null_mut(); 8];
+    for block in blocks.iter_mut() {
+        *block = unsafe { allocator.alloc(layout) };
+        assert!(!block.is_null());
+    }
+    for &index in [1, 3, 5, 7, 0, 2, 6, 4].iter() {
+        unsafe { allocator.dealloc(blocks[index], layout) };
+    }
+
+    // only possible if all freed regions were merged again
+    let whole = Layout::from_size_align(TEST_HEAP_SIZE, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(whole) };
+    assert_eq!(ptr as usize, heap_start);
+    assert_eq!(allocator.lock().stats().bytes_allocated, TEST_HEAP_SIZE);
+}
+
+#[test_case]
+fn linked_list_allocator_grows_in_place() {
+    let allocator = Locked::new(LinkedListAllocator::new());
+    let heap_start = unsafe { LINKED_LIST_HEAP.0.as_ptr() as usize };
+    unsafe { allocator.lock().init(heap_start, TEST_HEAP_SIZE) };
+
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(layout) };
+    unsafe { ptr.write_bytes(0x42, 64) };
+
+    let grown = unsafe { allocator.realloc(ptr, layout, 1024) };
+    assert_eq!(grown, ptr);
+    assert!((0..64).all(|i| unsafe { *grown.add(i) } == 0x42));
+}
+
+#[test_case]
+fn slab_cache_returns_empty_slabs() {
+    let free = || memory::with_kernel_memory(|_, frames| frames.free_frames()).unwrap();
+    let before = free();
+
+    let mut cache = SlabCache::new("test", 100, 8);
+    let mut objects = [None; 64];
+    for object in objects.iter_mut() {
+        *object = cache.allocate();
+        assert!(object.is_some());
+    }
+    assert!(cache.slabs() > 1);
+    assert_eq!(cache.objects_in_use(), 64);
+
+    for object in objects.iter() {
+        unsafe { cache.deallocate(object.unwrap()) };
+    }
+    assert_eq!(cache.slabs(), 0);
+    assert_eq!(free(), before);
+}
+
+const MAX_BLOCK_FRAMES: usize = 1 << MAX_ORDER;
+
+/// Runs `f` with a buddy allocator owning one block of `MAX_ORDER`, taken
+/// from the kernel's frame allocator and returned to it afterwards.
+fn with_buddy_block(f: impl FnOnce(&mut BuddyFrameAllocator, PhysFrame)) {
+    let block = memory::with_kernel_memory(|_, frames| {
+        frames.allocate_contiguous(MAX_BLOCK_FRAMES, MAX_BLOCK_FRAMES)
+    })
+    .unwrap()
+    .expect("no free 4 MiB block");
+    let start = block.start_address();
+    let end = start + (MAX_BLOCK_FRAMES * 4096) as u64;
+
+    let mut buddy = BuddyFrameAllocator::new(memory::phys_to_virt(PhysAddr::new(0)));
+    unsafe { buddy.add_region(start, end) };
+    assert_eq!(buddy.free_frames(), MAX_BLOCK_FRAMES);
+    f(&mut buddy, block);
+
+    memory::with_kernel_memory(|_, frames| {
+        for frame in PhysFrame::range(block, PhysFrame::containing_address(end)) {
+            unsafe { frames.deallocate_frame(frame) };
+        }
+    });
+}
+
+#[test_case]
+fn buddy_allocator_splits_and_coalesces() {
+    with_buddy_block(|buddy, block| {
+        // splitting the only block leaves one free block of every lower order
+        let first = buddy.allocate(0).unwrap();
+        assert_eq!(first, block);
+        assert_eq!(buddy.used_frames(), 1);
+        assert!(buddy.allocate(MAX_ORDER).is_none());
+        let second = buddy.allocate(0).unwrap();
+        assert_eq!(second, block + 1);
+
+        // the buddies merge back into the whole block
+        unsafe {
+            buddy.deallocate(first, 0);
+            buddy.deallocate(second, 0);
+        }
+        assert_eq!(buddy.free_frames(), MAX_BLOCK_FRAMES);
+        assert_eq!(buddy.allocate(MAX_ORDER), Some(block));
+        unsafe { buddy.deallocate(block, MAX_ORDER) };
+    });
+}
+
+#[test_case]
+fn buddy_blocks_are_naturally_aligned() {
+    with_buddy_block(|buddy, _| {
+        // a frame in front forces every larger block to be split off
+        let first = buddy.allocate(0).unwrap();
+        for order in 0..MAX_ORDER {
+            let block = buddy.allocate(order).expect("no block");
+            let frames = 1u64 << order;
+            assert_eq!(block.start_address().as_u64() / 4096 % frames, 0);
+            unsafe { buddy.deallocate(block, order) };
+        }
+        unsafe { buddy.deallocate(first, 0) };
+        let whole = buddy.allocate(MAX_ORDER).unwrap();
+        assert_eq!(
+            whole.start_address().as_u64() / 4096 % MAX_BLOCK_FRAMES as u64,
+            0
+        );
+        unsafe { buddy.deallocate(whole, MAX_ORDER) };
+    });
+}
+
+#[test_case]
+fn contiguous_frames_come_from_the_buddy_pool() {
+    let order = 3;
+    let block = memory::allocate_contiguous(order).expect("no contiguous frames");
+    assert_eq!(block.start_address().as_u64() % (4096 << order), 0);
+    let used = memory::with_kernel_memory(|_, frames| frames.is_used(block)).unwrap();
+    assert!(used);
+    unsafe { memory::deallocate_contiguous(block, order) };
+    assert!(memory::allocate_contiguous(MAX_ORDER + 1).is_none());
+}
+
+static TEST_OBJECTS: ObjectCache<[u64; 5]> = ObjectCache::new("test");
+static TEST_PAGES: ObjectCache<[u8; 4096]> = ObjectCache::new("test_page");
+
+#[test_case]
+fn object_cache_boxes_return_to_the_cache() {
+    let first = TEST_OBJECTS.alloc([1; 5]);
+    let second = TEST_OBJECTS.alloc([2; 5]);
+    assert_eq!(*first, [1; 5]);
+    assert_eq!(*second, [2; 5]);
+    assert_eq!(TEST_OBJECTS.objects_in_use(), 2);
+    assert_eq!(TEST_OBJECTS.slabs(), 1);
+
+    drop(first);
+    drop(second);
+    assert_eq!(TEST_OBJECTS.objects_in_use(), 0);
+    assert_eq!(TEST_OBJECTS.slabs(), 0);
+}
+
+#[test_case]
+fn page_sized_objects_take_whole_pages() {
+    let free = || memory::with_kernel_memory(|_, frames| frames.free_frames()).unwrap();
+    let before = free();
+
+    let page = TEST_PAGES.allocate_raw().unwrap();
+    assert_eq!(page.as_ptr() as usize % 4096, 0);
+    assert_eq!(free(), before - 1);
+    unsafe { TEST_PAGES.deallocate_raw(page) };
+    assert_eq!(free(), before);
+}
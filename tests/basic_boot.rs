@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::{hlt_loop, println, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+#[test_case]
+fn test_println() {
+    println!("test_println output");
+}
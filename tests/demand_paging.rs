@@ -0,0 +1,220 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use toy_os::kernel::exec::{self, USER_STACK_END, USER_STACK_SIZE};
+use toy_os::kernel::interrupts::exceptions::FAULT_EXIT_STATUS;
+use toy_os::kernel::memory;
+use toy_os::kernel::memory::address_space::{AddressSpace, MapError, USER_START};
+use toy_os::kernel::memory::vma::{Vma, VmaKind};
+use toy_os::kernel::process::MemoryFile;
+use toy_os::kernel::syscall::SYS_EXIT;
+use toy_os::testing::user::{self, addr, page, read_u64, Program};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::PageTableFlags;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START;
+const DATA: u64 = USER_START + 0x10_0000;
+const STACK_END: u64 = USER_START + 0x100_0000;
+const STACK_LIMIT: u64 = STACK_END - 0x4_0000;
+
+fn anonymous(start: u64, end: u64, flags: PageTableFlags) -> Vma {
+    Vma::new(addr(start), addr(end), flags, VmaKind::Anonymous)
+}
+
+fn stack() -> Vma {
+    let kind = VmaKind::Stack {
+        limit: addr(STACK_LIMIT),
+    };
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    Vma::new(addr(STACK_END - 0x1000), addr(STACK_END), flags, kind)
+}
+
+#[test_case]
+fn pages_are_mapped_on_first_touch() {
+    let mut space = AddressSpace::new().unwrap();
+    let vma = anonymous(DATA, DATA + 0x3000, PageTableFlags::WRITABLE);
+    space.add_vma(vma).unwrap();
+    assert!(space.translate(addr(DATA + 0x1000)).is_none());
+
+    assert_eq!(space.resolve_fault(page(DATA + 0x1000), true), Ok(true));
+    assert_eq!(read_u64(&mut space, DATA + 0x1000), 0);
+    let flags = space.page_flags(page(DATA + 0x1000)).unwrap();
+    assert!(flags.contains(PageTableFlags::WRITABLE));
+    assert!(space.translate(addr(DATA)).is_none());
+}
+
+#[test_case]
+fn faults_outside_of_areas_are_not_resolved() {
+    let mut space = AddressSpace::new().unwrap();
+    space
+        .add_vma(anonymous(DATA, DATA + 0x1000, PageTableFlags::empty()))
+        .unwrap();
+    assert_eq!(space.resolve_fault(page(DATA + 0x1000), false), Ok(false));
+    // the area is read-only
+    assert_eq!(space.resolve_fault(page(DATA), true), Ok(false));
+    assert_eq!(space.resolve_fault(page(DATA), false), Ok(true));
+}
+
+#[test_case]
+fn areas_must_not_overlap() {
+    let mut space = AddressSpace::new().unwrap();
+    let flags = PageTableFlags::WRITABLE;
+    space
+        .add_vma(anonymous(DATA, DATA + 0x2000, flags))
+        .unwrap();
+    assert_eq!(
+        space.add_vma(anonymous(DATA + 0x1000, DATA + 0x3000, flags)),
+        Err(MapError::AlreadyMapped)
+    );
+    space
+        .add_vma(anonymous(DATA + 0x2000, DATA + 0x3000, flags))
+        .unwrap();
+    space.add_vma(stack()).unwrap();
+    // the room the stack may grow into is reserved
+    assert_eq!(
+        space.add_vma(anonymous(STACK_LIMIT, STACK_LIMIT + 0x1000, flags)),
+        Err(MapError::AlreadyMapped)
+    );
+    assert_eq!(
+        space.add_vma(anonymous(0x1000, 0x2000, flags)),
+        Err(MapError::NotUserMemory)
+    );
+}
+
+#[test_case]
+fn file_backed_pages_hold_the_file() {
+    let data: Vec<u8> = (0..0x1800u32).map(|i| (i % 251) as u8).collect();
+    let file = Arc::new(MemoryFile::new(data.clone()));
+    let mut space = AddressSpace::new().unwrap();
+    let kind = VmaKind::File { file, offset: 8 };
+    let vma = Vma::new(
+        addr(DATA),
+        addr(DATA + 0x2000),
+        PageTableFlags::empty(),
+        kind,
+    );
+    space.add_vma(vma).unwrap();
+
+    assert_eq!(space.resolve_fault(page(DATA + 0x1000), false), Ok(true));
+    let phys = space.translate(addr(DATA + 0x1000)).unwrap();
+    let mapped =
+        unsafe { core::slice::from_raw_parts(memory::phys_to_virt(phys).as_ptr::<u8>(), 0x1000) };
+    let len = data.len() - 0x1008;
+    assert_eq!(&mapped[..len], &data[0x1008..]);
+    // past the end of the file
+    assert!(mapped[len..].iter().all(|&byte| byte == 0));
+}
+
+#[test_case]
+fn stacks_grow_down_to_their_limit() {
+    let mut space = AddressSpace::new().unwrap();
+    space.add_vma(stack()).unwrap();
+
+    assert_eq!(
+        space.resolve_fault(page(STACK_END - 0x2_0000), true),
+        Ok(true)
+    );
+    let vma = space.vmas().find(addr(STACK_END - 0x1_0000)).unwrap();
+    assert_eq!(vma.start, addr(STACK_END - 0x2_0000));
+    assert_eq!(space.resolve_fault(page(STACK_LIMIT), true), Ok(true));
+    assert_eq!(space.resolve_fault(page(STACK_LIMIT - 1), true), Ok(false));
+}
+
+#[test_case]
+fn exec_maps_the_stack_on_demand() {
+    // the smallest image: `hlt` at the entry point
+    let image = elf_with_code(&[0xf4]);
+    let mut loaded = exec::load(&image, &["prog"], &[]).unwrap();
+    let space = &mut loaded.addr_space;
+    assert!(space.translate(loaded.stack_pointer).is_some());
+    let bottom = addr(USER_STACK_END - USER_STACK_SIZE);
+    assert!(space.translate(bottom).is_none());
+    assert!(space.vmas().find(bottom).is_some());
+}
+
+/// An executable with `code` at `CODE`.
+fn elf_with_code(code: &[u8]) -> Vec<u8> {
+    let mut image = Vec::new();
+    // ELF header: 64-bit, little endian, version 1, executable, x86_64
+    image.extend_from_slice(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+    image.extend_from_slice(&2u16.to_le_bytes());
+    image.extend_from_slice(&0x3eu16.to_le_bytes());
+    image.extend_from_slice(&1u32.to_le_bytes());
+    image.extend_from_slice(&CODE.to_le_bytes()); // entry
+    image.extend_from_slice(&64u64.to_le_bytes()); // phoff
+    image.extend_from_slice(&0u64.to_le_bytes()); // shoff
+    image.extend_from_slice(&0u32.to_le_bytes()); // flags
+    image.extend_from_slice(&64u16.to_le_bytes()); // ehsize
+    image.extend_from_slice(&56u16.to_le_bytes()); // phentsize
+    image.extend_from_slice(&1u16.to_le_bytes()); // phnum
+    image.extend_from_slice(&[0; 6]); // shentsize, shnum, shstrndx
+
+    // PT_LOAD, readable and executable, with the code after the header
+    let offset = 64 + 56;
+    image.extend_from_slice(&1u32.to_le_bytes());
+    image.extend_from_slice(&5u32.to_le_bytes());
+    image.extend_from_slice(&(offset as u64).to_le_bytes());
+    image.extend_from_slice(&CODE.to_le_bytes()); // vaddr
+    image.extend_from_slice(&CODE.to_le_bytes()); // paddr
+    image.extend_from_slice(&(code.len() as u64).to_le_bytes()); // filesz
+    image.extend_from_slice(&(code.len() as u64).to_le_bytes()); // memsz
+    image.extend_from_slice(&0x1000u64.to_le_bytes()); // align
+    image.extend_from_slice(code);
+    image
+}
+
+/// Runs `program` with an untouched writable area at `DATA` and a growing
+/// stack.
+fn run(program: Program) -> usize {
+    let mut space = AddressSpace::new().unwrap();
+    let code_flags = PageTableFlags::empty();
+    space
+        .add_vma(anonymous(CODE, CODE + 0x1000, code_flags))
+        .unwrap();
+    space.write(addr(CODE), program.code()).unwrap();
+    let data_flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    space
+        .add_vma(anonymous(DATA, DATA + 0x1000, data_flags))
+        .unwrap();
+    space.add_vma(stack()).unwrap();
+    user::run(space, CODE, STACK_END)
+}
+
+/// Stores 5 at `DATA`, moves the stack pointer down by `depth`, pushes the
+/// value and exits with it plus the value at `DATA`.
+fn touch_program(depth: u32) -> Program {
+    Program::default()
+        .mov_rax(DATA)
+        // mov qword ptr [rax], 5
+        .bytes(&[0x48, 0xc7, 0x00, 5, 0, 0, 0])
+        // sub rsp, depth
+        .bytes(&[0x48, 0x81, 0xec])
+        .bytes(&depth.to_le_bytes())
+        // push qword ptr [rax]; pop rdi; add rdi, [rax]
+        .bytes(&[0xff, 0x30, 0x5f, 0x48, 0x03, 0x38])
+        .syscall(SYS_EXIT)
+}
+
+#[test_case]
+fn user_code_touches_untouched_memory() {
+    assert_eq!(run(touch_program(0x2_0000)), 10);
+}
+
+#[test_case]
+fn stack_overflow_terminates_the_process() {
+    assert_eq!(run(touch_program(0x8_0000)), FAULT_EXIT_STATUS);
+}
@@ -0,0 +1,231 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use toy_os::kernel::exec::{self, ExecError, USER_STACK_END};
+use toy_os::kernel::interrupts::exceptions::FAULT_EXIT_STATUS;
+use toy_os::kernel::memory::address_space::USER_START;
+use toy_os::kernel::syscall::SYS_EXIT;
+use toy_os::testing::user::{page, read_u64, wait, Program};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::PageTableFlags;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START + 0x40_0000;
+const DATA: u64 = USER_START + 0x60_0000;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+struct Segment {
+    vaddr: u64,
+    flags: u32,
+    data: Vec<u8>,
+    memsz: u64,
+}
+
+/// Builds an ELF64 executable with `segments`, starting at `entry`.
+fn build(entry: u64, segments: &[Segment]) -> Vec<u8> {
+    let phoff = 64;
+    let mut offset = phoff + 56 * segments.len();
+    let mut image = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    image.extend_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+    image.extend_from_slice(&0x3eu16.to_le_bytes()); // x86_64
+    image.extend_from_slice(&1u32.to_le_bytes());
+    image.extend_from_slice(&entry.to_le_bytes());
+    image.extend_from_slice(&(phoff as u64).to_le_bytes());
+    image.extend_from_slice(&0u64.to_le_bytes()); // no section headers
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&64u16.to_le_bytes());
+    image.extend_from_slice(&56u16.to_le_bytes());
+    image.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+    image.extend_from_slice(&[0; 6]);
+
+    for segment in segments {
+        image.extend_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+        image.extend_from_slice(&segment.flags.to_le_bytes());
+        image.extend_from_slice(&(offset as u64).to_le_bytes());
+        image.extend_from_slice(&segment.vaddr.to_le_bytes());
+        image.extend_from_slice(&segment.vaddr.to_le_bytes());
+        image.extend_from_slice(&(segment.data.len() as u64).to_le_bytes());
+        image.extend_from_slice(&segment.memsz.to_le_bytes());
+        image.extend_from_slice(&0x1000u64.to_le_bytes());
+        offset += segment.data.len();
+    }
+    for segment in segments {
+        image.extend_from_slice(&segment.data);
+    }
+    image
+}
+
+fn code(bytes: &[u8]) -> Segment {
+    Segment {
+        vaddr: CODE,
+        flags: PF_R | PF_X,
+        data: bytes.to_vec(),
+        memsz: bytes.len() as u64,
+    }
+}
+
+fn data(bytes: &[u8], memsz: u64) -> Segment {
+    Segment {
+        vaddr: DATA,
+        flags: PF_R | PF_W,
+        data: bytes.to_vec(),
+        memsz,
+    }
+}
+
+fn run(image: &[u8], args: &[&str], env: &[&str]) -> usize {
+    wait(exec::spawn("test", image, args, env).expect("could not start"))
+}
+
+#[test_case]
+fn program_gets_argc() {
+    let program = Program::default()
+        // mov rax, [rsp]
+        .bytes(&[0x48, 0x8b, 0x04, 0x24])
+        .exit_with_result();
+    let image = build(CODE, &[code(program.code())]);
+    assert_eq!(run(&image, &["prog", "a", "b"], &[]), 3);
+}
+
+#[test_case]
+fn program_gets_argv_and_envp() {
+    let first_byte_at = |offset: u8| {
+        let program = Program::default()
+            // mov rax, [rsp + offset]; movzx eax, byte ptr [rax]
+            .bytes(&[0x48, 0x8b, 0x44, 0x24, offset, 0x0f, 0xb6, 0x00])
+            .exit_with_result();
+        build(CODE, &[code(program.code())])
+    };
+    // argv[1]
+    assert_eq!(
+        run(&first_byte_at(16), &["prog", "hello"], &[]),
+        usize::from(b'h')
+    );
+    // envp[0], after argc, argv[0] and the null pointer
+    assert_eq!(
+        run(&first_byte_at(24), &["prog"], &["X=1"]),
+        usize::from(b'X')
+    );
+}
+
+#[test_case]
+fn stack_holds_auxiliary_vector() {
+    let image = build(CODE, &[code(&[0xf4])]);
+    let mut loaded = exec::load(&image, &["prog"], &[]).unwrap();
+    let sp = loaded.stack_pointer.as_u64();
+    assert_eq!(sp % 16, 0);
+    assert!(sp < USER_STACK_END);
+
+    let space = &mut loaded.addr_space;
+    assert_eq!(read_u64(space, sp), 1);
+    assert_eq!(read_u64(space, sp + 16), 0);
+    assert_eq!(read_u64(space, sp + 24), 0);
+
+    let mut auxv = Vec::new();
+    let mut addr = sp + 32;
+    loop {
+        let (kind, value) = (read_u64(space, addr), read_u64(space, addr + 8));
+        if kind == 0 {
+            break;
+        }
+        auxv.push((kind, value));
+        addr += 16;
+    }
+    assert!(auxv.contains(&(6, 4096))); // AT_PAGESZ
+    assert!(auxv.contains(&(9, CODE))); // AT_ENTRY
+    assert!(auxv.contains(&(5, 1))); // AT_PHNUM
+}
+
+#[test_case]
+fn segments_get_their_permissions() {
+    let image = build(CODE, &[code(&[0xf4]), data(&[1], 0x10)]);
+    let loaded = exec::load(&image, &[], &[]).unwrap();
+    let flags = |addr| {
+        loaded
+            .addr_space
+            .page_flags(page(addr))
+            .expect("not mapped")
+    };
+    let code_flags = flags(CODE);
+    assert!(!code_flags.contains(PageTableFlags::WRITABLE));
+    assert!(!code_flags.contains(PageTableFlags::NO_EXECUTE));
+    let data_flags = flags(DATA);
+    assert!(data_flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+    assert!(data_flags.contains(PageTableFlags::USER_ACCESSIBLE));
+}
+
+#[test_case]
+fn data_is_loaded_and_bss_zeroed() {
+    let bss = DATA + 0x1800;
+    let program = Program::default()
+        // mov rax, [DATA]; mov rdi, rax
+        .bytes(&[0x48, 0xa1])
+        .bytes(&DATA.to_le_bytes())
+        .bytes(&[0x48, 0x89, 0xc7])
+        // mov rax, [bss]; add rdi, rax
+        .bytes(&[0x48, 0xa1])
+        .bytes(&bss.to_le_bytes())
+        .bytes(&[0x48, 0x01, 0xc7])
+        .syscall(SYS_EXIT);
+
+    let image = build(
+        CODE,
+        &[code(program.code()), data(&42u64.to_le_bytes(), 0x2000)],
+    );
+    assert_eq!(run(&image, &[], &[]), 42);
+}
+
+#[test_case]
+fn code_is_not_writable() {
+    // mov [CODE], al
+    let program = Program::default().bytes(&[0xa2]).bytes(&CODE.to_le_bytes());
+    let image = build(CODE, &[code(program.code())]);
+    assert_eq!(run(&image, &[], &[]), FAULT_EXIT_STATUS);
+}
+
+#[test_case]
+fn data_is_not_executable() {
+    // jmp rax
+    let program = Program::default().mov_rax(DATA).bytes(&[0xff, 0xe0]);
+    let image = build(CODE, &[code(program.code()), data(&[0xc3], 1)]);
+    assert_eq!(run(&image, &[], &[]), FAULT_EXIT_STATUS);
+}
+
+#[test_case]
+fn bad_images_are_rejected() {
+    let load = |image: &[u8]| exec::load(image, &[], &[]).map(|_| ());
+    let valid = build(CODE, &[code(&[0xf4])]);
+
+    assert!(matches!(load(b"#!/bin/sh"), Err(ExecError::BadImage(_))));
+    let mut wrong_machine = valid.clone();
+    wrong_machine[18] = 0x28; // ARM
+    assert!(matches!(load(&wrong_machine), Err(ExecError::BadImage(_))));
+    assert!(matches!(
+        load(&valid[..valid.len() - 1]),
+        Err(ExecError::BadImage(_))
+    ));
+
+    let mut kernel_segment = code(&[0xf4]);
+    kernel_segment.vaddr = 0x20_0000;
+    let image = build(0x20_0000, &[kernel_segment]);
+    assert_eq!(load(&image), Err(ExecError::BadSegment));
+
+    let image = build(DATA, &[code(&[0xf4]), data(&[0xf4], 1)]);
+    assert!(matches!(load(&image), Err(ExecError::BadImage(_))));
+}
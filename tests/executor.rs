@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use toy_os::task::{executor::Executor, Task};
+use toy_os::{hlt_loop, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+/// Returns `Pending` once, waking itself before doing so.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+async fn count(counter: Arc<AtomicUsize>, yields: usize) {
+    for _ in 0..yields {
+        YieldNow(false).await;
+    }
+    counter.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn runs_spawned_tasks() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    for _ in 0..10 {
+        executor.spawn(Task::new(count(counter.clone(), 0)));
+    }
+    executor.run_until_idle();
+    assert_eq!(counter.load(Ordering::SeqCst), 10);
+}
+
+#[test_case]
+fn woken_tasks_are_polled_again() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(count(counter.clone(), 3)));
+    executor.spawn(Task::new(count(counter.clone(), 5)));
+    executor.run_until_idle();
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+}
+
+#[test_case]
+fn finished_tasks_are_dropped() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(count(counter.clone(), 1)));
+    executor.run_until_idle();
+    // the task owned the only other reference
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
@@ -0,0 +1,146 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::kernel::memory::address_space::{AddressSpace, COPY_ON_WRITE, USER_START};
+use toy_os::kernel::memory::frame_is_shared;
+use toy_os::kernel::process;
+use toy_os::kernel::syscall::{SYS_EXIT, SYS_FORK, SYS_WAITPID};
+use toy_os::testing::user::{self, addr, page, read_u64, Program};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::{PageTableFlags, PhysFrame};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START;
+const DATA: u64 = USER_START + 0x1000;
+const STACK_END: u64 = USER_START + 0x3000;
+
+fn frame_of(space: &mut AddressSpace, address: u64) -> PhysFrame {
+    PhysFrame::containing_address(space.translate(addr(address)).expect("not mapped"))
+}
+
+/// An address space with `value` in a writable page at `DATA` and a
+/// read-only page at `CODE`.
+fn data_space(value: u64) -> AddressSpace {
+    let mut space = AddressSpace::new().expect("out of memory");
+    space.map(page(CODE), PageTableFlags::empty()).unwrap();
+    space.map(page(DATA), PageTableFlags::WRITABLE).unwrap();
+    space.write(addr(DATA), &value.to_le_bytes()).unwrap();
+    space
+}
+
+#[test_case]
+fn fork_shares_frames_copy_on_write() {
+    let mut parent = data_space(5);
+    let mut child = parent.fork().expect("could not fork");
+
+    let frame = frame_of(&mut parent, DATA);
+    assert_eq!(frame_of(&mut child, DATA), frame);
+    assert!(frame_is_shared(frame));
+    assert_eq!(read_u64(&mut child, DATA), 5);
+    for space in [&parent, &child].iter() {
+        let flags = space.page_flags(page(DATA)).unwrap();
+        assert!(flags.contains(COPY_ON_WRITE));
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+    }
+}
+
+#[test_case]
+fn read_only_pages_are_shared_read_only() {
+    let mut parent = data_space(0);
+    let child = parent.fork().expect("could not fork");
+    for space in [&parent, &child].iter() {
+        let flags = space.page_flags(page(CODE)).unwrap();
+        assert!(!flags.contains(COPY_ON_WRITE));
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+    }
+}
+
+#[test_case]
+fn copy_on_write_copies_shared_frames() {
+    let mut parent = data_space(5);
+    let mut child = parent.fork().expect("could not fork");
+    let shared = frame_of(&mut parent, DATA);
+
+    assert_eq!(child.copy_on_write(page(DATA)), Ok(true));
+    let copy = frame_of(&mut child, DATA);
+    assert_ne!(copy, shared);
+    assert_eq!(read_u64(&mut child, DATA), 5);
+    let flags = child.page_flags(page(DATA)).unwrap();
+    assert!(flags.contains(PageTableFlags::WRITABLE));
+    assert!(!flags.contains(COPY_ON_WRITE));
+
+    // the parent is the only user left, so it keeps the frame
+    assert!(!frame_is_shared(shared));
+    assert_eq!(parent.copy_on_write(page(DATA)), Ok(true));
+    assert_eq!(frame_of(&mut parent, DATA), shared);
+    assert_eq!(parent.copy_on_write(page(DATA)), Ok(false));
+}
+
+#[test_case]
+fn writes_do_not_reach_the_other_space() {
+    let mut parent = data_space(5);
+    let mut child = parent.fork().expect("could not fork");
+    child.write(addr(DATA), &42u64.to_le_bytes()).unwrap();
+    assert_eq!(read_u64(&mut child, DATA), 42);
+    assert_eq!(read_u64(&mut parent, DATA), 5);
+}
+
+#[test_case]
+fn dropping_a_copy_releases_its_references() {
+    let mut parent = data_space(5);
+    let frame = frame_of(&mut parent, DATA);
+    let first = parent.fork().expect("could not fork");
+    let second = parent.fork().expect("could not fork");
+    drop(first);
+    assert!(frame_is_shared(frame));
+    drop(second);
+    assert!(!frame_is_shared(frame));
+    assert_eq!(read_u64(&mut parent, DATA), 5);
+}
+
+/// Forks; the child stores 42 at `DATA` and exits with 7, the parent waits for
+/// it with the status at `DATA + 8` and exits with `[DATA] * 10 + [DATA + 8]`.
+fn fork_program() -> Program {
+    Program::default()
+        .syscall(SYS_FORK)
+        // test rax, rax; jnz parent
+        .bytes(&[0x48, 0x85, 0xc0, 0x75, 29])
+        // child
+        .mov_rax(DATA)
+        // mov qword ptr [rax], 42
+        .bytes(&[0x48, 0xc7, 0x00, 42, 0, 0, 0])
+        .mov_edi(7)
+        .syscall(SYS_EXIT)
+        // parent: mov rdi, rax
+        .bytes(&[0x48, 0x89, 0xc7])
+        .mov_rsi(DATA + 8)
+        // xor edx, edx
+        .bytes(&[0x31, 0xd2])
+        .syscall(SYS_WAITPID)
+        .mov_rax(DATA)
+        // mov rdi, [rax]; imul rdi, rdi, 10; add rdi, [rax + 8]
+        .bytes(&[0x48, 0x8b, 0x38, 0x48, 0x6b, 0xff, 10, 0x48, 0x03, 0x78, 8])
+        .syscall(SYS_EXIT)
+}
+
+#[test_case]
+fn forked_child_writes_its_own_copy() {
+    let mut space = data_space(5);
+    space
+        .map(page(STACK_END - 0x1000), PageTableFlags::WRITABLE)
+        .unwrap();
+    space.write(addr(CODE), fork_program().code()).unwrap();
+
+    assert_eq!(user::run(space, CODE, STACK_END), 5 * 10 + 7);
+    // the child was reaped by its parent
+    assert_eq!(process::processes().len(), 0);
+}
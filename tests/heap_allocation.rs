@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use toy_os::kernel::allocator::{heap_stats, HEAP_SIZE};
+use toy_os::kernel::memory;
+use toy_os::{hlt_loop, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+#[test_case]
+fn many_boxes() {
+    for i in 0..HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+#[test_case]
+fn many_boxes_long_lived() {
+    let long_lived = Box::new(1); // new
+    for i in 0..HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+    assert_eq!(*long_lived, 1); // new
+}
+
+#[test_case]
+fn heap_grows_beyond_initial_size() {
+    let size = 4 * HEAP_SIZE;
+    let mut vec = Vec::with_capacity(size);
+    vec.resize(size, 0xab_u8);
+    assert!(vec.iter().all(|&b| b == 0xab));
+    assert!(heap_stats().heap_size > HEAP_SIZE);
+}
+
+#[test_case]
+fn stats_track_allocations() {
+    let before = heap_stats();
+    let value = Box::new([0u8; 64]);
+    let during = heap_stats();
+    assert_eq!(during.bytes_allocated, before.bytes_allocated + 64);
+    assert_eq!(during.allocations, before.allocations + 1);
+    assert!(during.peak_allocated >= during.bytes_allocated);
+
+    drop(value);
+    let after = heap_stats();
+    assert_eq!(after.bytes_allocated, before.bytes_allocated);
+    assert_eq!(after.live_allocations(), before.live_allocations());
+}
+
+#[test_case]
+fn frames_are_recycled() {
+    let free = || memory::with_kernel_memory(|_, frames| frames.free_frames()).unwrap();
+    let before = free();
+
+    let first = memory::allocate_frame().expect("out of frames");
+    let second = memory::allocate_frame().expect("out of frames");
+    assert_ne!(first, second);
+    assert_eq!(free(), before - 2);
+
+    unsafe {
+        memory::deallocate_frame(first);
+        memory::deallocate_frame(second);
+    }
+    assert_eq!(free(), before);
+}
@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use toy_os::kernel::devices::apic;
+use toy_os::kernel::interrupts::{self, exceptions::SelectorErrorCode, irq};
+use toy_os::testing::ShouldPanic;
+use toy_os::{hlt_loop, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+#[test_case]
+fn kernel_code_segment_loaded() {
+    use x86_64::instructions::segmentation;
+    use x86_64::PrivilegeLevel;
+
+    let cs = segmentation::cs();
+    assert_ne!(cs.index(), 0);
+    assert_eq!(cs.rpl(), PrivilegeLevel::Ring0);
+}
+
+#[test_case]
+fn breakpoint_exception_returns() {
+    x86_64::instructions::interrupts::int3();
+}
+
+#[test_case]
+fn timer_interrupts_arrive() {
+    let start = interrupts::ticks();
+    while interrupts::ticks() < start + 2 {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn apic_replaced_pics() {
+    assert!(apic::is_enabled());
+    let cpuid = unsafe { core::arch::x86_64::__cpuid(1) };
+    assert_eq!(apic::local::id(), cpuid.ebx >> 24);
+}
+
+#[test_case]
+fn timer_irq_counted() {
+    let start = irq::count(irq::TIMER);
+    while irq::count(irq::TIMER) < start + 2 {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn irq_lines_claimed() {
+    assert_eq!(
+        irq::register(irq::TIMER, "test", || {}),
+        Err(irq::IrqError::Busy)
+    );
+    assert_eq!(
+        irq::register_shared(irq::TIMER, "test", || {}),
+        Err(irq::IrqError::Busy)
+    );
+    assert_eq!(
+        irq::register(irq::IRQ_LINES as u8, "test", || {}),
+        Err(irq::IrqError::InvalidLine)
+    );
+}
+
+#[test_case]
+fn shared_irq_handlers_called() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // line 3 is the unused second serial port
+    let first = irq::register_shared(3, "test", || {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    })
+    .unwrap();
+    let second = irq::register_shared(3, "test", || {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    })
+    .unwrap();
+    assert_eq!(irq::register(3, "test", || {}), Err(irq::IrqError::Busy));
+
+    let count = irq::count(3);
+    unsafe { asm!("int {}", const irq::IRQ_BASE + 3) };
+    assert_eq!(irq::count(3), count + 1);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    irq::unregister(first);
+    irq::unregister(second);
+    unsafe { asm!("int {}", const irq::IRQ_BASE + 3) };
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[test_case]
+fn selector_error_code_decoded() {
+    // index 5 of the IDT
+    let code = SelectorErrorCode(5 << 3 | 0b10);
+    assert_eq!(code.table(), "IDT");
+    assert_eq!(code.index(), 5);
+    assert!(!code.external());
+
+    // index 2 of the LDT, raised by an external event
+    let code = SelectorErrorCode(2 << 3 | 0b101);
+    assert_eq!(code.table(), "LDT");
+    assert_eq!(code.index(), 2);
+    assert!(code.external());
+}
+
+#[test_case]
+static INVALID_OPCODE_PANICS: ShouldPanic = ShouldPanic {
+    name: "interrupts::invalid_opcode_panics",
+    test: || unsafe { asm!("ud2") },
+};
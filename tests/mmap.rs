@@ -0,0 +1,206 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::kernel::interrupts::exceptions::FAULT_EXIT_STATUS;
+use toy_os::kernel::memory::address_space::{
+    AddressSpace, MapError, COPY_ON_WRITE, MMAP_START, USER_START,
+};
+use toy_os::kernel::memory::vma::{Vma, VmaKind};
+use toy_os::kernel::syscall::{
+    MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, SYS_EXIT, SYS_MMAP, SYS_MPROTECT, SYS_MUNMAP,
+};
+use toy_os::testing::user::{self, addr, page, Program};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::PageTableFlags;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START;
+const STACK_END: u64 = USER_START + 0x10_0000;
+
+fn writable() -> PageTableFlags {
+    PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE
+}
+
+fn anonymous(space: &mut AddressSpace, len: u64) -> u64 {
+    let start = space.mmap(None, len, writable(), VmaKind::Anonymous);
+    start.unwrap().as_u64()
+}
+
+#[test_case]
+fn mmap_finds_free_addresses() {
+    let mut space = AddressSpace::new().unwrap();
+    let first = anonymous(&mut space, 0x1800);
+    assert_eq!(first, MMAP_START);
+    // the length is rounded up to whole pages
+    assert_eq!(anonymous(&mut space, 0x1000), first + 0x2000);
+
+    let hint = Some(addr(first));
+    let kind = VmaKind::Anonymous;
+    assert_eq!(
+        space.mmap(hint, 0x1000, writable(), kind.clone()),
+        Err(MapError::AlreadyMapped)
+    );
+    let hint = Some(addr(first + 0x1_0000));
+    assert_eq!(
+        space.mmap(hint, 0x1000, writable(), kind.clone()),
+        Ok(addr(first + 0x1_0000))
+    );
+    assert_eq!(
+        space.mmap(None, 0, writable(), kind),
+        Err(MapError::BadRange)
+    );
+}
+
+#[test_case]
+fn munmap_unmaps_part_of_an_area() {
+    let mut space = AddressSpace::new().unwrap();
+    let start = anonymous(&mut space, 0x3000);
+    for offset in (0..0x3000).step_by(0x1000) {
+        assert_eq!(space.resolve_fault(page(start + offset), true), Ok(true));
+    }
+
+    space.munmap(addr(start + 0x1000), 0x1000).unwrap();
+    assert!(space.translate(addr(start + 0x1000)).is_none());
+    assert!(space.translate(addr(start)).is_some());
+    assert!(space.translate(addr(start + 0x2000)).is_some());
+    // the hole is not part of an area anymore
+    assert!(space.vmas().find(addr(start + 0x1000)).is_none());
+    assert_eq!(space.resolve_fault(page(start + 0x1000), false), Ok(false));
+    assert_eq!(space.vmas().iter().count(), 2);
+
+    // unmapping nothing is fine
+    space.munmap(addr(start + 0x1000), 0x1000).unwrap();
+    assert_eq!(
+        space.munmap(addr(start + 1), 0x1000),
+        Err(MapError::BadRange)
+    );
+}
+
+#[test_case]
+fn mprotect_changes_mapped_and_untouched_pages() {
+    let mut space = AddressSpace::new().unwrap();
+    let start = anonymous(&mut space, 0x2000);
+    assert_eq!(space.resolve_fault(page(start), true), Ok(true));
+
+    let read_only = PageTableFlags::NO_EXECUTE;
+    space.mprotect(addr(start), 0x2000, read_only).unwrap();
+    let flags = space.page_flags(page(start)).unwrap();
+    assert!(!flags.contains(PageTableFlags::WRITABLE));
+    assert_eq!(space.resolve_fault(page(start), true), Ok(false));
+    assert_eq!(space.resolve_fault(page(start + 0x1000), true), Ok(false));
+
+    // only part of the range is in an area
+    assert_eq!(
+        space.mprotect(addr(start), 0x3000, writable()),
+        Err(MapError::NotMapped)
+    );
+}
+
+#[test_case]
+fn mprotect_keeps_shared_frames_copy_on_write() {
+    let mut space = AddressSpace::new().unwrap();
+    let start = anonymous(&mut space, 0x1000);
+    space.write(addr(start), &[7]).unwrap();
+    let mut child = space.fork().unwrap();
+
+    space
+        .mprotect(addr(start), 0x1000, PageTableFlags::NO_EXECUTE)
+        .unwrap();
+    space.mprotect(addr(start), 0x1000, writable()).unwrap();
+    let flags = space.page_flags(page(start)).unwrap();
+    assert!(flags.contains(COPY_ON_WRITE));
+    assert!(!flags.contains(PageTableFlags::WRITABLE));
+
+    assert_eq!(space.resolve_fault(page(start), true), Ok(true));
+    assert_ne!(space.translate(addr(start)), child.translate(addr(start)));
+}
+
+/// Runs `program` with a stack, but no memory to store anything in.
+fn run(program: Program) -> usize {
+    let mut space = AddressSpace::new().unwrap();
+    let code_vma = Vma::new(
+        addr(CODE),
+        addr(CODE + 0x1000),
+        PageTableFlags::empty(),
+        VmaKind::Anonymous,
+    );
+    space.add_vma(code_vma).unwrap();
+    space.write(addr(CODE), program.code()).unwrap();
+    let stack = Vma::new(
+        addr(STACK_END - 0x1000),
+        addr(STACK_END),
+        writable(),
+        VmaKind::Anonymous,
+    );
+    space.add_vma(stack).unwrap();
+    user::run(space, CODE, STACK_END)
+}
+
+/// Maps a writable page, keeps its address in `rbx` and stores 42 in it.
+fn map_and_store() -> Program {
+    Program::default()
+        // xor edi, edi
+        .bytes(&[0x31, 0xff])
+        .mov_esi(0x1000)
+        .mov_edx((PROT_READ | PROT_WRITE) as u32)
+        .mov_r10d((MAP_PRIVATE | MAP_ANONYMOUS) as u32)
+        // xor r8d, r8d; xor r9d, r9d
+        .bytes(&[0x45, 0x31, 0xc0, 0x45, 0x31, 0xc9])
+        .syscall(SYS_MMAP)
+        // mov rbx, rax; mov qword ptr [rbx], 42
+        .bytes(&[0x48, 0x89, 0xc3, 0x48, 0xc7, 0x03, 42, 0, 0, 0])
+}
+
+/// Calls `number` with the page at `rbx` and `arg` as arguments.
+fn call_with_page(program: Program, number: usize, arg: usize) -> Program {
+    program
+        // mov rdi, rbx
+        .bytes(&[0x48, 0x89, 0xdf])
+        .mov_esi(0x1000)
+        .mov_edx(arg as u32)
+        .syscall(number)
+}
+
+/// Exits with the value at `rbx`.
+fn exit_with_value(program: Program) -> Program {
+    program
+        // mov rdi, [rbx]
+        .bytes(&[0x48, 0x8b, 0x3b])
+        .syscall(SYS_EXIT)
+}
+
+/// `mov qword ptr [rbx], 1`
+const STORE: [u8; 7] = [0x48, 0xc7, 0x03, 1, 0, 0, 0];
+
+#[test_case]
+fn user_code_maps_memory() {
+    let program = exit_with_value(map_and_store());
+    assert_eq!(run(program), 42);
+}
+
+#[test_case]
+fn read_only_memory_can_be_read() {
+    let program = call_with_page(map_and_store(), SYS_MPROTECT, PROT_READ);
+    assert_eq!(run(exit_with_value(program)), 42);
+}
+
+#[test_case]
+fn writes_to_read_only_memory_fault() {
+    let program = call_with_page(map_and_store(), SYS_MPROTECT, PROT_READ).bytes(&STORE);
+    assert_eq!(run(exit_with_value(program)), FAULT_EXIT_STATUS);
+}
+
+#[test_case]
+fn unmapped_memory_faults() {
+    let program = call_with_page(map_and_store(), SYS_MUNMAP, 0).bytes(&STORE);
+    assert_eq!(run(exit_with_value(program)), FAULT_EXIT_STATUS);
+}
@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::kernel::context;
+use toy_os::kernel::interrupts::exceptions::FAULT_EXIT_STATUS;
+use toy_os::kernel::memory::address_space::{AddressSpace, USER_START};
+use toy_os::kernel::process::{self, ProcessId, ProcessStatus, STDOUT};
+use toy_os::kernel::syscall::{Error, ECHILD, SYS_EXIT, SYS_SLEEP};
+use toy_os::testing::user::{self, addr, page, Program};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::PageTableFlags;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START;
+const STACK_END: u64 = USER_START + 0x2000;
+
+/// Starts `program` in a new process.
+fn start(program: Program) -> ProcessId {
+    let mut space = AddressSpace::new().expect("out of memory");
+    space.map(page(CODE), PageTableFlags::empty()).unwrap();
+    space
+        .map(page(STACK_END - 0x1000), PageTableFlags::WRITABLE)
+        .unwrap();
+    space.write(addr(CODE), program.code()).unwrap();
+    user::start(space, CODE, STACK_END)
+}
+
+/// A program exiting with `status` after sleeping for `ms` milliseconds.
+fn exit_after(ms: u32, status: u32) -> Program {
+    let mut program = Program::default();
+    if ms > 0 {
+        program = program.mov_edi(ms).syscall(SYS_SLEEP);
+    }
+    program.mov_edi(status).syscall(SYS_EXIT)
+}
+
+#[test_case]
+fn waitpid_returns_exit_status() {
+    let id = start(exit_after(0, 5));
+    assert_eq!(process::waitpid(Some(id), false), Ok(Some((id, 5))));
+    assert!(process::processes().get(id).is_none());
+}
+
+#[test_case]
+fn process_starts_with_standard_files() {
+    let id = start(exit_after(200, 0));
+    assert!(process::processes()
+        .get(id)
+        .unwrap()
+        .files
+        .get(STDOUT)
+        .is_some());
+    process::waitpid(Some(id), false).unwrap();
+}
+
+#[test_case]
+fn nohang_does_not_block() {
+    let id = start(exit_after(200, 3));
+    assert_eq!(process::waitpid(Some(id), true), Ok(None));
+    assert_eq!(process::waitpid(Some(id), false), Ok(Some((id, 3))));
+}
+
+#[test_case]
+fn zombies_are_kept_until_reaped() {
+    let id = start(exit_after(0, 9));
+    let thread = process::processes().get(id).unwrap().threads[0];
+    loop {
+        let status = process::processes().get(id).unwrap().status;
+        if status == ProcessStatus::Zombie(9) {
+            break;
+        }
+        context::yield_now();
+    }
+    {
+        let processes = process::processes();
+        let zombie = processes.get(id).unwrap();
+        assert!(zombie.addr_space.is_none());
+        assert!(zombie.files.get(STDOUT).is_none());
+    }
+
+    assert_eq!(process::waitpid(None, false), Ok(Some((id, 9))));
+    assert!(process::processes().get(id).is_none());
+    assert!(context::contexts().get(thread).is_none());
+}
+
+#[test_case]
+fn waitpid_reaps_any_child() {
+    let first = start(exit_after(0, 1));
+    let second = start(exit_after(50, 2));
+    let mut reaped = [
+        process::waitpid(None, false).unwrap().unwrap(),
+        process::waitpid(None, false).unwrap().unwrap(),
+    ];
+    reaped.sort();
+    assert_eq!(reaped, [(first, 1), (second, 2)]);
+}
+
+#[test_case]
+fn waitpid_without_children_fails() {
+    assert_eq!(process::waitpid(None, false), Err(Error::new(ECHILD)));
+    let id = start(exit_after(0, 0));
+    let other = ProcessId::new(id.as_usize() + 1);
+    assert_eq!(
+        process::waitpid(Some(other), false),
+        Err(Error::new(ECHILD))
+    );
+    process::waitpid(Some(id), false).unwrap();
+}
+
+#[test_case]
+fn faults_terminate_the_process() {
+    // hlt is privileged in ring 3
+    let id = start(Program::default().bytes(&[0xf4]));
+    let thread = process::processes().get(id).unwrap().threads[0];
+    assert_eq!(
+        process::waitpid(Some(id), false),
+        Ok(Some((id, FAULT_EXIT_STATUS)))
+    );
+    assert!(context::contexts().get(thread).is_none());
+}
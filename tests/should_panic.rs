@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::testing::ShouldPanic;
+use toy_os::{hlt_loop, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+#[test_case]
+static FAILING_ASSERTION: ShouldPanic = ShouldPanic {
+    name: "should_panic::failing_assertion",
+    test: || assert_eq!(0, 1),
+};
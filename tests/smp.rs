@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+#![feature(const_in_array_repeat_expressions)]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use toy_os::kernel::{percpu, smp};
+use toy_os::{hlt_loop, per_cpu, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+#[test_case]
+fn all_cpus_started() {
+    // the tests run with `-smp 4`, see `Cargo.toml`
+    assert_eq!(smp::cpu_count(), 4);
+}
+
+#[test_case]
+fn bootstrap_processor_is_cpu_0() {
+    assert_eq!(toy_os::cpu_id(), 0);
+    assert_eq!(percpu::current().cpu_id(), 0);
+    assert!(!percpu::current().in_interrupt());
+}
+
+#[test_case]
+fn every_cpu_has_a_per_cpu_block() {
+    for cpu_id in 0..smp::cpu_count() {
+        let cpu = percpu::get(cpu_id).expect("CPU without per-CPU block");
+        assert_eq!(cpu.cpu_id(), cpu_id);
+    }
+    assert!(percpu::get(smp::cpu_count()).is_none());
+}
+
+per_cpu! {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+}
+
+#[test_case]
+fn per_cpu_statics_are_separate() {
+    COUNTER.get().fetch_add(1, Ordering::SeqCst);
+    assert_eq!(COUNTER.get_for(0).load(Ordering::SeqCst), 1);
+    assert_eq!(COUNTER.get_for(1).load(Ordering::SeqCst), 0);
+}
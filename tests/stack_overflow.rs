@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use lazy_static::lazy_static;
+use toy_os::qemu::{exit_qemu, QemuExitCode};
+use toy_os::{serial_print, serial_println, userspace_entrypoint};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    serial_print!("stack_overflow::stack_overflow...\t");
+
+    // the test IDT has no handlers for hardware interrupts
+    x86_64::instructions::interrupts::disable();
+    init_test_idt();
+
+    // trigger a stack overflow
+    stack_overflow();
+
+    panic!("Execution continued after stack overflow");
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow(); // for each recursion, the return address is pushed
+    volatile::Volatile::new(0).read(); // prevent tail recursion optimizations
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(toy_os::kernel::devices::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+
+        idt
+    };
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_double_fault_handler(
+    _stack_frame: &mut InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+}
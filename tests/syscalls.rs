@@ -0,0 +1,133 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use toy_os::kernel::interrupts;
+use toy_os::kernel::memory::address_space::{AddressSpace, USER_START};
+use toy_os::kernel::process::ProcessId;
+use toy_os::kernel::syscall::*;
+use toy_os::testing::user::{self, addr, page, wait, Program};
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::PageTableFlags;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START;
+const DATA: u64 = USER_START + 0x1000;
+const STACK_END: u64 = USER_START + 0x3000;
+
+/// Starts `program` with `data` at `DATA` in a new process.
+fn start(program: Program, data: &[u8]) -> ProcessId {
+    let mut space = AddressSpace::new().expect("out of memory");
+    space.map(page(CODE), PageTableFlags::empty()).unwrap();
+    space.map(page(DATA), PageTableFlags::WRITABLE).unwrap();
+    space
+        .map(page(STACK_END - 0x1000), PageTableFlags::WRITABLE)
+        .unwrap();
+    space.write(addr(CODE), program.code()).unwrap();
+    space.write(addr(DATA), data).unwrap();
+    user::start(space, CODE, STACK_END)
+}
+
+/// Runs `program` with `data` at `DATA` and returns its exit status.
+fn run(program: Program, data: &[u8]) -> usize {
+    wait(start(program, data))
+}
+
+fn error(errno: i32) -> usize {
+    Error::mux(Err(Error::new(errno)))
+}
+
+#[test_case]
+fn exit_status_is_passed_on() {
+    let program = Program::default().mov_edi(7).syscall(SYS_EXIT);
+    assert_eq!(run(program, &[]), 7);
+}
+
+#[test_case]
+fn getpid_returns_process_id() {
+    let program = Program::default().syscall(SYS_GETPID).exit_with_result();
+    let id = start(program, &[]);
+    assert_eq!(wait(id), id.as_usize());
+}
+
+#[test_case]
+fn waitpid_without_children_fails() {
+    let program = Program::default()
+        // mov rdi, -1
+        .bytes(&[0x48, 0xc7, 0xc7, 0xff, 0xff, 0xff, 0xff])
+        .mov_rsi(0)
+        .mov_edx(0)
+        .syscall(SYS_WAITPID)
+        .exit_with_result();
+    assert_eq!(run(program, &[]), error(ECHILD));
+}
+
+fn write(fd: u32, address: u64, len: u32) -> Program {
+    Program::default()
+        .mov_edi(fd)
+        .mov_rsi(address)
+        .mov_edx(len)
+        .syscall(SYS_WRITE)
+        .exit_with_result()
+}
+
+#[test_case]
+fn write_prints_user_buffer() {
+    assert_eq!(run(write(1, DATA, 6), b"hello\n"), 6);
+}
+
+static KERNEL_DATA: [u8; 8] = *b"secrets\n";
+
+#[test_case]
+fn write_rejects_bad_buffers() {
+    let kernel = KERNEL_DATA.as_ptr() as u64;
+    assert_eq!(run(write(1, kernel, 8), &[]), error(EFAULT));
+    // crosses into the unmapped page after the data
+    assert_eq!(run(write(1, DATA + 0xff0, 0x20), &[]), error(EFAULT));
+    assert_eq!(run(write(7, DATA, 1), b"x"), error(EBADF));
+}
+
+#[test_case]
+fn unknown_syscall_fails() {
+    let program = Program::default().syscall(99).exit_with_result();
+    assert_eq!(run(program, &[]), error(ENOSYS));
+}
+
+#[test_case]
+fn yield_returns() {
+    let program = Program::default().syscall(SYS_YIELD).exit_with_result();
+    assert_eq!(run(program, &[]), 0);
+}
+
+#[test_case]
+fn sleep_blocks_for_the_duration() {
+    let program = Program::default()
+        .mov_edi(300)
+        .syscall(SYS_SLEEP)
+        .exit_with_result();
+    let start = interrupts::ticks();
+    assert_eq!(run(program, &[]), 0);
+    let elapsed = interrupts::ticks() - start;
+    assert!(elapsed * 1000 >= 300 * interrupts::TICKS_PER_SECOND);
+}
+
+#[test_case]
+fn registers_are_preserved() {
+    let program = Program::default()
+        // mov r8d, 77
+        .bytes(&[0x41, 0xb8])
+        .bytes(&77u32.to_le_bytes())
+        .syscall(SYS_GETPID)
+        // mov rdi, r8
+        .bytes(&[0x4c, 0x89, 0xc7])
+        .syscall(SYS_EXIT);
+    assert_eq!(run(program, &[]), 77);
+}
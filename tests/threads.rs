@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use toy_os::kernel::context::{self, ContextId, Status};
+use toy_os::kernel::interrupts;
+use toy_os::{hlt_loop, userspace_entrypoint};
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+/// Yields until the context `id` exited and returns its status.
+fn wait_for_exit(id: ContextId) -> usize {
+    context::reap(id).expect("context vanished")
+}
+
+#[test_case]
+fn boot_context_is_running() {
+    let id = context::context_id().expect("no current context");
+    let contexts = context::contexts();
+    let context = contexts.get(id).unwrap().read();
+    assert!(context.running);
+    assert_eq!(context.status, Status::Runnable);
+    assert_eq!(context.cpu_id, Some(0));
+}
+
+static RAN: AtomicBool = AtomicBool::new(false);
+
+#[test_case]
+fn spawned_thread_runs_and_exits() {
+    let id = context::spawn("test", || RAN.store(true, Ordering::SeqCst)).unwrap();
+    assert_eq!(wait_for_exit(id), 0);
+    assert!(RAN.load(Ordering::SeqCst));
+    assert!(context::contexts().get(id).is_none());
+}
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static SPINS: AtomicUsize = AtomicUsize::new(0);
+
+#[test_case]
+fn timer_preempts_busy_threads() {
+    // neither thread yields, so only the timer lets the other one run
+    let id = context::spawn("spinner", || {
+        while !STOP.load(Ordering::SeqCst) {
+            SPINS.fetch_add(1, Ordering::SeqCst);
+        }
+    })
+    .unwrap();
+    while SPINS.load(Ordering::SeqCst) == 0 {
+        core::sync::atomic::spin_loop_hint();
+    }
+    STOP.store(true, Ordering::SeqCst);
+    wait_for_exit(id);
+}
+
+static WOKEN: AtomicBool = AtomicBool::new(false);
+static SLEEPER: AtomicUsize = AtomicUsize::new(0);
+
+#[test_case]
+fn blocked_thread_waits_for_unblock() {
+    let id = context::spawn("sleeper", || {
+        SLEEPER.store(context::context_id().unwrap().as_usize(), Ordering::SeqCst);
+        context::block("test");
+        WOKEN.store(true, Ordering::SeqCst);
+    })
+    .unwrap();
+
+    while context::contexts().get(id).unwrap().read().status != Status::Blocked {
+        context::yield_now();
+    }
+    assert_eq!(SLEEPER.load(Ordering::SeqCst), id.as_usize());
+    for _ in 0..10 {
+        context::yield_now();
+    }
+    assert!(!WOKEN.load(Ordering::SeqCst));
+
+    assert!(context::unblock(id));
+    wait_for_exit(id);
+    assert!(WOKEN.load(Ordering::SeqCst));
+}
+
+#[test_case]
+fn exit_status_is_kept() {
+    let id = context::spawn("exit", || context::exit(42)).unwrap();
+    assert_eq!(wait_for_exit(id), 42);
+}
+
+static STOP_BLOCKING: AtomicBool = AtomicBool::new(false);
+
+/// Blocks and wakes up again until `STOP_BLOCKING` is set.
+fn block_repeatedly() {
+    while !STOP_BLOCKING.load(Ordering::SeqCst) {
+        context::sleep_until(interrupts::ticks() + 1);
+    }
+}
+
+#[test_case]
+fn threads_spawn_while_others_block() {
+    // blocking looks up the current context, and must not be preempted while
+    // it holds the list that `spawn` and `reap` wait for
+    let blockers = [
+        context::spawn("blocker", block_repeatedly).unwrap(),
+        context::spawn("blocker", block_repeatedly).unwrap(),
+        context::spawn("blocker", block_repeatedly).unwrap(),
+    ];
+    for _ in 0..100 {
+        let id = context::spawn("short", || ()).unwrap();
+        assert_eq!(wait_for_exit(id), 0);
+    }
+    STOP_BLOCKING.store(true, Ordering::SeqCst);
+    for &id in blockers.iter() {
+        assert_eq!(wait_for_exit(id), 0);
+    }
+}
@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(toy_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use toy_os::kernel::interrupts::exceptions::FAULT_EXIT_STATUS;
+use toy_os::kernel::memory::{self, address_space::AddressSpace, address_space::USER_START};
+use toy_os::kernel::process;
+use toy_os::{hlt_loop, userspace_entrypoint};
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
+use x86_64::VirtAddr;
+
+userspace_entrypoint!(test_kernel_main);
+
+fn test_kernel_main() -> ! {
+    test_main();
+    hlt_loop();
+}
+
+const CODE: u64 = USER_START;
+const DATA: u64 = USER_START + 0x1000;
+const STACK_END: u64 = USER_START + 0x3000;
+
+/// Creates an address space with `code` at `CODE`, and a data and a stack page.
+fn load(code: &[u8]) -> (AddressSpace, PhysFrame) {
+    let mut space = AddressSpace::new().expect("out of memory");
+    let page = |addr| Page::containing_address(VirtAddr::new(addr));
+    let code_frame = space.map(page(CODE), PageTableFlags::empty()).unwrap();
+    let data_frame = space.map(page(DATA), PageTableFlags::WRITABLE).unwrap();
+    space
+        .map(page(STACK_END - 0x1000), PageTableFlags::WRITABLE)
+        .unwrap();
+
+    let code_page = memory::phys_to_virt(code_frame.start_address());
+    unsafe {
+        let dest = code_page.as_mut_ptr::<u8>();
+        core::ptr::copy_nonoverlapping(code.as_ptr(), dest, code.len());
+    }
+    (space, data_frame)
+}
+
+/// Runs `space` in a new process and returns its exit status.
+fn run(space: AddressSpace) -> usize {
+    let id = process::spawn_user("user", space, VirtAddr::new(CODE), VirtAddr::new(STACK_END))
+        .expect("could not spawn");
+    let (_, status) = process::waitpid(Some(id), false)
+        .expect("no such child")
+        .expect("did not wait");
+    status
+}
+
+/// `mov rax, imm64` followed by `mov qword ptr [rax], imm32`.
+fn store(addr: u64, value: u32) -> [u8; 17] {
+    let mut code = [0; 17];
+    code[..2].copy_from_slice(&[0x48, 0xb8]);
+    code[2..10].copy_from_slice(&addr.to_le_bytes());
+    code[10..13].copy_from_slice(&[0x48, 0xc7, 0x00]);
+    code[13..].copy_from_slice(&value.to_le_bytes());
+    code
+}
+
+#[test_case]
+fn privileged_instructions_fault() {
+    // hlt; jmp back to hlt - loops forever in ring 0
+    let (space, _) = load(&[0xf4, 0xeb, 0xfd]);
+    assert_eq!(run(space), FAULT_EXIT_STATUS);
+}
+
+#[test_case]
+fn user_code_writes_user_memory() {
+    let mut code = [0xf4; 18];
+    code[..17].copy_from_slice(&store(DATA, 42));
+    let (space, data) = load(&code);
+    assert_eq!(run(space), FAULT_EXIT_STATUS);
+
+    let data = memory::phys_to_virt(data.start_address());
+    assert_eq!(unsafe { *data.as_ptr::<u64>() }, 42);
+}
+
+static KERNEL_DATA: AtomicU64 = AtomicU64::new(0);
+
+#[test_case]
+fn user_code_cannot_write_kernel_memory() {
+    let mut code = [0xf4; 18];
+    code[..17].copy_from_slice(&store(&KERNEL_DATA as *const _ as u64, 42));
+    let (space, _) = load(&code);
+    assert_eq!(run(space), FAULT_EXIT_STATUS);
+    assert_eq!(KERNEL_DATA.load(Ordering::SeqCst), 0);
+}
+
+#[test_case]
+fn address_spaces_are_separate() {
+    let (mut first, _) = load(&[0xf4]);
+    let (mut second, _) = load(&[0xf4]);
+    assert_ne!(first.page_table(), second.page_table());
+
+    let user = VirtAddr::new(CODE);
+    assert_ne!(first.translate(user), second.translate(user));
+
+    // the kernel is mapped in both
+    let kernel = VirtAddr::from_ptr(&KERNEL_DATA);
+    assert!(first.translate(kernel).is_some());
+    assert_eq!(first.translate(kernel), second.translate(kernel));
+}
@@ -0,0 +1,30 @@
+#!/bin/sh
+# Writes the symbol table of a kernel ELF into its reserved `.ksyms` section,
+# which `kernel::backtrace` uses to symbolize return addresses.
+#
+# usage: tools/embed-symbols.sh <kernel-elf>
+#
+# Set NM and OBJCOPY to use other binutils (e.g. `rust-nm`, `rust-objcopy`).
+set -e
+
+kernel="$1"
+nm="${NM:-nm}"
+objcopy="${OBJCOPY:-objcopy}"
+
+symbols="$(mktemp)"
+section="$(mktemp)"
+trap 'rm -f "$symbols" "$section"' EXIT
+
+"$nm" --defined-only -n -C "$kernel" | grep ' [Tt] ' > "$symbols"
+
+"$objcopy" -O binary --only-section=.ksyms "$kernel" "$section"
+reserved="$(wc -c < "$section")"
+used="$(wc -c < "$symbols")"
+if [ "$used" -ge "$reserved" ]; then
+    echo "embed-symbols: $used bytes of symbols do not fit into $reserved bytes" >&2
+    exit 1
+fi
+
+# keep the section size, so that no addresses change
+truncate -s "$reserved" "$symbols"
+"$objcopy" --update-section .ksyms="$symbols" "$kernel"
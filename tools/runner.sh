@@ -0,0 +1,6 @@
+#!/bin/sh
+# Cargo runner: embeds the kernel symbol table, then boots through bootimage.
+set -e
+
+"$(dirname "$0")/embed-symbols.sh" "$1"
+exec bootimage runner "$@"